@@ -0,0 +1,310 @@
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga_oil::compose::{Composer, NagaModuleDescriptor, ShaderDefValue};
+use std::collections::HashMap;
+
+/// Naga capabilities wgpu would validate against on this adapter, mirroring wgpu-core.
+pub fn capabilities(adapter: &wgpu::Adapter) -> Capabilities {
+    let features = adapter.features();
+    let downlevel = adapter.get_downlevel_capabilities().flags;
+
+    let mut caps = Capabilities::empty();
+    caps.set(
+        Capabilities::PUSH_CONSTANT,
+        features.contains(wgpu::Features::PUSH_CONSTANTS),
+    );
+    caps.set(
+        Capabilities::FLOAT64,
+        features.contains(wgpu::Features::SHADER_F64),
+    );
+    caps.set(
+        Capabilities::PRIMITIVE_INDEX,
+        features.contains(wgpu::Features::SHADER_PRIMITIVE_INDEX),
+    );
+    caps.set(
+        Capabilities::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+            | Capabilities::SAMPLER_NON_UNIFORM_INDEXING,
+        features.contains(
+            wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+        ),
+    );
+    caps.set(
+        Capabilities::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+        features.contains(
+            wgpu::Features::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
+        ),
+    );
+    caps.set(
+        Capabilities::STORAGE_TEXTURE_16BIT_NORM_FORMATS,
+        features.contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM),
+    );
+    caps.set(
+        Capabilities::MULTIVIEW,
+        features.contains(wgpu::Features::MULTIVIEW),
+    );
+    caps.set(
+        Capabilities::EARLY_DEPTH_TEST,
+        features.contains(wgpu::Features::SHADER_EARLY_DEPTH_TEST),
+    );
+    caps.set(
+        Capabilities::MULTISAMPLED_SHADING,
+        downlevel.contains(wgpu::DownlevelFlags::MULTISAMPLED_SHADING),
+    );
+    caps.set(
+        Capabilities::DUAL_SOURCE_BLENDING,
+        features.contains(wgpu::Features::DUAL_SOURCE_BLENDING),
+    );
+    caps.set(
+        Capabilities::CUBE_ARRAY_TEXTURES,
+        downlevel.contains(wgpu::DownlevelFlags::CUBE_ARRAY_TEXTURES),
+    );
+
+    caps
+}
+
+/// Per-adapter composition result.
+struct AdapterOutput {
+    name: String,
+    capabilities: Capabilities,
+    spirv: Result<Vec<u32>, String>,
+}
+
+/// Compose the shader with the given capabilities and emit SPIR-V for it.
+fn compose_spirv(
+    source: &str,
+    shader_defs: &HashMap<String, ShaderDefValue>,
+    caps: Capabilities,
+) -> Result<Vec<u32>, String> {
+    let mut composer = Composer::default().with_capabilities(caps);
+
+    let module = composer
+        .make_naga_module(NagaModuleDescriptor {
+            source,
+            shader_defs: shader_defs.clone(),
+            ..Default::default()
+        })
+        .map_err(|e| e.emit_to_string(&composer))?;
+
+    // The composer has validated already, but the backend needs the module info.
+    let info = Validator::new(ValidationFlags::all(), caps)
+        .validate(&module)
+        .map_err(|e| e.as_inner().to_string())?;
+
+    naga::back::spv::write_vec(&module, &info, &Default::default(), None).map_err(|e| e.to_string())
+}
+
+/// Compose the shader once per available adapter, using the capabilities each adapter
+/// offers, and report where the emitted SPIR-V diverges from the first adapter's.
+///
+/// Returns `false` if composition failed on any adapter or the outputs differ.
+pub fn compare(source: &str, shader_defs: &HashMap<String, ShaderDefValue>) -> bool {
+    let instance = wgpu::Instance::default();
+
+    let outputs: Vec<AdapterOutput> = instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .map(|adapter| {
+            let info = adapter.get_info();
+            let capabilities = capabilities(&adapter);
+
+            AdapterOutput {
+                name: format!("{} ({:?})", info.name, info.backend),
+                capabilities,
+                spirv: compose_spirv(source, shader_defs, capabilities),
+            }
+        })
+        .collect();
+
+    let Some(reference) = outputs.first() else {
+        println!("No adapters found");
+        return false;
+    };
+
+    let mut consistent = true;
+
+    for output in &outputs {
+        println!("{}", output.name);
+        println!("    capabilities: {:?}", output.capabilities);
+
+        match &output.spirv {
+            Ok(words) => println!("    SPIR-V: {} words", words.len()),
+            Err(e) => {
+                println!("    failed:\n{e}");
+                consistent = false;
+                continue;
+            }
+        }
+
+        if std::ptr::eq(output, reference) {
+            continue;
+        }
+
+        let missing = reference.capabilities - output.capabilities;
+        let extra = output.capabilities - reference.capabilities;
+        if !missing.is_empty() || !extra.is_empty() {
+            println!(
+                "    vs {}: missing {:?}, extra {:?}",
+                reference.name, missing, extra
+            );
+        }
+
+        if let (Ok(words), Ok(reference_words)) = (&output.spirv, &reference.spirv) {
+            match words.iter().zip(reference_words).position(|(a, b)| a != b) {
+                None if words.len() == reference_words.len() => {
+                    println!("    SPIR-V identical to {}", reference.name)
+                }
+                position => {
+                    let position =
+                        position.unwrap_or_else(|| words.len().min(reference_words.len()));
+                    println!(
+                        "    SPIR-V diverges from {} at word {}",
+                        reference.name, position
+                    );
+                    consistent = false;
+                }
+            }
+        }
+    }
+
+    consistent
+}
+
+/// Reads one field of `wgpu::Limits`.
+type LimitField = fn(&wgpu::Limits) -> u64;
+
+/// Every field of `wgpu::Limits` by name, to list the limits of an adapter and a device
+/// side by side.
+const LIMITS: &[(&str, LimitField)] = &[
+    ("max_texture_dimension_1d", |limits| {
+        limits.max_texture_dimension_1d.into()
+    }),
+    ("max_texture_dimension_2d", |limits| {
+        limits.max_texture_dimension_2d.into()
+    }),
+    ("max_texture_dimension_3d", |limits| {
+        limits.max_texture_dimension_3d.into()
+    }),
+    ("max_texture_array_layers", |limits| {
+        limits.max_texture_array_layers.into()
+    }),
+    ("max_bind_groups", |limits| limits.max_bind_groups.into()),
+    ("max_bindings_per_bind_group", |limits| {
+        limits.max_bindings_per_bind_group.into()
+    }),
+    (
+        "max_dynamic_uniform_buffers_per_pipeline_layout",
+        |limits| {
+            limits
+                .max_dynamic_uniform_buffers_per_pipeline_layout
+                .into()
+        },
+    ),
+    (
+        "max_dynamic_storage_buffers_per_pipeline_layout",
+        |limits| {
+            limits
+                .max_dynamic_storage_buffers_per_pipeline_layout
+                .into()
+        },
+    ),
+    ("max_sampled_textures_per_shader_stage", |limits| {
+        limits.max_sampled_textures_per_shader_stage.into()
+    }),
+    ("max_samplers_per_shader_stage", |limits| {
+        limits.max_samplers_per_shader_stage.into()
+    }),
+    ("max_storage_buffers_per_shader_stage", |limits| {
+        limits.max_storage_buffers_per_shader_stage.into()
+    }),
+    ("max_storage_textures_per_shader_stage", |limits| {
+        limits.max_storage_textures_per_shader_stage.into()
+    }),
+    ("max_uniform_buffers_per_shader_stage", |limits| {
+        limits.max_uniform_buffers_per_shader_stage.into()
+    }),
+    ("max_uniform_buffer_binding_size", |limits| {
+        limits.max_uniform_buffer_binding_size.into()
+    }),
+    ("max_storage_buffer_binding_size", |limits| {
+        limits.max_storage_buffer_binding_size.into()
+    }),
+    ("max_vertex_buffers", |limits| {
+        limits.max_vertex_buffers.into()
+    }),
+    ("max_buffer_size", |limits| limits.max_buffer_size),
+    ("max_vertex_attributes", |limits| {
+        limits.max_vertex_attributes.into()
+    }),
+    ("max_vertex_buffer_array_stride", |limits| {
+        limits.max_vertex_buffer_array_stride.into()
+    }),
+    ("min_uniform_buffer_offset_alignment", |limits| {
+        limits.min_uniform_buffer_offset_alignment.into()
+    }),
+    ("min_storage_buffer_offset_alignment", |limits| {
+        limits.min_storage_buffer_offset_alignment.into()
+    }),
+    ("max_inter_stage_shader_components", |limits| {
+        limits.max_inter_stage_shader_components.into()
+    }),
+    ("max_compute_workgroup_storage_size", |limits| {
+        limits.max_compute_workgroup_storage_size.into()
+    }),
+    ("max_compute_invocations_per_workgroup", |limits| {
+        limits.max_compute_invocations_per_workgroup.into()
+    }),
+    ("max_compute_workgroup_size_x", |limits| {
+        limits.max_compute_workgroup_size_x.into()
+    }),
+    ("max_compute_workgroup_size_y", |limits| {
+        limits.max_compute_workgroup_size_y.into()
+    }),
+    ("max_compute_workgroup_size_z", |limits| {
+        limits.max_compute_workgroup_size_z.into()
+    }),
+    ("max_compute_workgroups_per_dimension", |limits| {
+        limits.max_compute_workgroups_per_dimension.into()
+    }),
+    ("max_push_constant_size", |limits| {
+        limits.max_push_constant_size.into()
+    }),
+    ("max_non_sampler_bindings", |limits| {
+        limits.max_non_sampler_bindings.into()
+    }),
+];
+
+/// Print what the adapter `gpu` was created from is, the optional features it supports,
+/// its limits next to the ones the device was requested with, and its downlevel
+/// capabilities.
+pub fn print_info(gpu: &crate::render::Gpu) {
+    let info = gpu.adapter.get_info();
+    println!("{} ({:?}, {:?})", info.name, info.backend, info.device_type);
+    println!(
+        "    vendor {:#06x}, device {:#06x}",
+        info.vendor, info.device
+    );
+    if !info.driver.is_empty() {
+        println!("    driver {} {}", info.driver, info.driver_info);
+    }
+
+    println!("features:");
+    for (name, _) in gpu.adapter.features().iter_names() {
+        println!("    {name}");
+    }
+
+    println!("limits (adapter / device):");
+    let (adapter_limits, device_limits) = (gpu.adapter.limits(), gpu.device.limits());
+    for (name, limit) in LIMITS {
+        let (adapter, device) = (limit(&adapter_limits), limit(&device_limits));
+        if adapter == device {
+            println!("    {name}: {adapter}");
+        } else {
+            println!("    {name}: {adapter} / {device}");
+        }
+    }
+
+    let downlevel = gpu.adapter.get_downlevel_capabilities();
+    println!("downlevel capabilities:");
+    println!("    shader model {:?}", downlevel.shader_model);
+    for (name, _) in downlevel.flags.iter_names() {
+        println!("    {name}");
+    }
+}
@@ -0,0 +1,32 @@
+// Sample shader for `--frame-uniforms`, cycling colours over time and across the target.
+
+struct Frame {
+    resolution: vec2<f32>,
+    time: f32,
+    _pad: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> frame: Frame;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = in.clip_position.xy / frame.resolution;
+    let color = 0.5 + 0.5 * cos(frame.time + uv.xyx + vec3<f32>(0.0, 2.0, 4.0));
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,148 @@
+use crate::render::{self, FrameUniforms, Gpu, RenderError, RenderOptions};
+use wgpu::util::DeviceExt;
+
+/// Render `frames` frames of a shader reading `FrameUniforms` at `@group(0) @binding(0)`,
+/// its time starting at `start` seconds and advancing by `1 / fps` every frame, and hand
+/// the tightly packed pixels of each to `on_frame` in order.
+///
+/// The pipeline and the target are made once, only the uniforms are rewritten between
+/// frames. Every frame is submitted and read back before the next one is drawn, so only
+/// one is held at a time. Only `size`, `format`, `clear_color`, `blend`, `blend_constant`,
+/// `readback`, `timeout` and the entry points of the options apply.
+pub fn render_frames<E: From<RenderError>>(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    options: &RenderOptions,
+    frames: u32,
+    fps: f32,
+    start: f32,
+    mut on_frame: impl FnMut(u32, Vec<u8>) -> Result<(), E>,
+) -> Result<(), E> {
+    let device = &gpu.device;
+
+    if let wgpu::ShaderSource::Naga(module) = &shader_source {
+        render::check_entry_point(module, naga::ShaderStage::Vertex, options.vertex_entry)?;
+        render::check_entry_point(module, naga::ShaderStage::Fragment, options.fragment_entry)?;
+    }
+    if options.blend_constant.is_none() && options.blend.is_some_and(render::uses_blend_constant) {
+        return Err(RenderError::MissingBlendConstant.into());
+    }
+
+    let pixel_size = options
+        .format
+        .block_size(None)
+        .ok_or(RenderError::UnsupportedFormat(options.format))?;
+    options
+        .readback
+        .bytes_per_row(pixel_size * options.size.0)?;
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("animation texture"),
+        size: wgpu::Extent3d {
+            width: options.size.0,
+            height: options.size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: options.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("frame uniform buffer"),
+        contents: bytemuck::bytes_of(&FrameUniforms::new(start, options.size)),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("frame bind group layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("frame bind group"),
+        layout: &bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniform_buffer.as_entire_binding(),
+        }],
+    });
+
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline_options = RenderOptions {
+        format: options.format,
+        blend: options.blend,
+        vertex_entry: options.vertex_entry,
+        fragment_entry: options.fragment_entry,
+        ..Default::default()
+    };
+    let pipeline = render::capture_validation(device, || {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: shader_source,
+        });
+        render::quad_pipeline(device, &shader, &layout, options.format, &pipeline_options)
+    })
+    .map_err(RenderError::from)?;
+    let (vertex_buffer, index_buffer) = render::quad_buffers(device);
+
+    let load = options
+        .clear_color
+        .map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear);
+
+    for frame in 0..frames {
+        let uniforms = FrameUniforms::new(start + frame as f32 / fps, options.size);
+        // Written ahead of the submission below, so this frame's draw sees it.
+        gpu.queue
+            .write_buffer(&uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("animation encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("animation pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(&pipeline);
+            if let Some(color) = options.blend_constant {
+                render_pass.set_blend_constant(color);
+            }
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..6, 0, 0..1);
+        }
+
+        let data = render::submit_read_back(gpu, encoder, &texture, options.readback)?
+            .wait_for(device, options.timeout)?;
+        on_frame(frame, data)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use crate::shader_maker::{self, ShaderMaker};
+use naga_oil::compose::ShaderDefValue;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Outcome of composing one shader, the diagnostic if it failed.
+pub struct BatchResult {
+    pub path: PathBuf,
+    pub error: Option<String>,
+    /// How long reading and composing it took.
+    pub duration: Duration,
+}
+
+/// Every result of a batch, in the sorted order of the paths.
+pub struct Batch {
+    pub results: Vec<BatchResult>,
+    /// Wall-clock time of the whole batch.
+    pub elapsed: Duration,
+    pub jobs: usize,
+}
+
+fn compile(
+    shader_maker: &mut ShaderMaker,
+    path: PathBuf,
+    shader_defs: &HashMap<String, ShaderDefValue>,
+) -> BatchResult {
+    let start = Instant::now();
+    let error = shader_maker
+        .make_module_from_file(&path, shader_defs.clone())
+        .err()
+        .map(|e| shader_maker.diagnostic(&e));
+
+    BatchResult {
+        path,
+        error,
+        duration: start.elapsed(),
+    }
+}
+
+/// Compose and validate every `.wgsl` file below `dir` on `jobs` threads, without
+/// rendering anything.
+///
+/// Modules the shaders import have to be registered with `shader_maker` beforehand, every
+/// thread gets a `ShaderMaker::fork` of it. Threads take the next file when they are done
+/// with one, and a failure doesn't stop the rest.
+pub fn run(
+    shader_maker: &ShaderMaker,
+    dir: &Path,
+    shader_defs: &HashMap<String, ShaderDefValue>,
+    jobs: usize,
+) -> std::io::Result<Batch> {
+    let start = Instant::now();
+
+    let mut paths = Vec::new();
+    shader_maker::wgsl_files(dir, &mut paths)?;
+    let jobs = jobs.clamp(1, paths.len().max(1));
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, BatchResult)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                let (paths, next) = (&paths, &next);
+                scope.spawn(move || {
+                    // Forking costs as much as registering the modules, do it in parallel too.
+                    let mut shader_maker = shader_maker.fork();
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(path) = paths.get(index) else {
+                            return results;
+                        };
+                        results
+                            .push((index, compile(&mut shader_maker, path.clone(), shader_defs)));
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("a batch thread panicked"))
+            .collect()
+    });
+    results.sort_unstable_by_key(|(index, _)| *index);
+
+    Ok(Batch {
+        results: results.into_iter().map(|(_, result)| result).collect(),
+        elapsed: start.elapsed(),
+        jobs,
+    })
+}
+
+/// Print a line per shader, the diagnostics of those that failed, and a summary with the
+/// speedup over composing one after the other.
+///
+/// Returns `false` if any shader failed.
+pub fn print_report(batch: &Batch) -> bool {
+    for result in &batch.results {
+        match &result.error {
+            None => println!("[ok]   {}", result.path.display()),
+            Some(error) => println!("[fail] {}\n{error}", result.path.display()),
+        }
+    }
+
+    let failed = batch
+        .results
+        .iter()
+        .filter(|result| result.error.is_some())
+        .count();
+    // Roughly what a single thread would have taken, without forking makers.
+    let serial: Duration = batch.results.iter().map(|result| result.duration).sum();
+    println!(
+        "{} of {} shaders compiled, {failed} failed, in {:?} on {} thread{}, a {:.1}x speedup over the {:?} they took one after the other",
+        batch.results.len() - failed,
+        batch.results.len(),
+        batch.elapsed,
+        batch.jobs,
+        if batch.jobs == 1 { "" } else { "s" },
+        serial.as_secs_f64() / batch.elapsed.as_secs_f64(),
+        serial
+    );
+
+    failed == 0
+}
@@ -0,0 +1,110 @@
+use crate::render::{self, Gpu, RenderOptions};
+use crate::shader_maker::ShaderMaker;
+use naga_oil::compose::ShaderDefValue;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+fn mean(times: &[Duration]) -> Duration {
+    times.iter().sum::<Duration>() / times.len() as u32
+}
+
+fn print_stats(label: &str, times: &[Duration]) {
+    let mean = mean(times);
+    let min = times.iter().min().copied().unwrap_or_default();
+    let max = times.iter().max().copied().unwrap_or_default();
+
+    println!("{label:<11} mean {mean:>12?}  min {min:>12?}  max {max:>12?}");
+}
+
+/// Compose, build the pipeline, render and read back `iterations` times, then report
+/// the time per stage and the frame rate that corresponds to.
+///
+/// The device and the composer, with its registered modules, are shared by all iterations,
+/// as an application would keep them. Composed modules aren't cached, so every iteration
+/// really composes. One warm-up frame isn't counted. Composition is then timed again
+/// without naga validation, to report what `--skip-validation` saves.
+///
+/// Returns `false` if the shader failed to compose or render.
+pub fn run(
+    gpu: &Gpu,
+    source: &str,
+    shader_defs: &HashMap<String, ShaderDefValue>,
+    iterations: u32,
+) -> bool {
+    let options = RenderOptions::default();
+    let mut shader_maker = ShaderMaker::new();
+    // Every iteration composes the same shader, which the cache would turn into a clone.
+    shader_maker.set_caching(false);
+
+    let mut compose_times = Vec::new();
+    let mut render_times = Vec::new();
+
+    let start = Instant::now();
+
+    for iteration in 0..=iterations {
+        if iteration == 1 {
+            compose_times.clear();
+            render_times.clear();
+        }
+
+        let compose_start = Instant::now();
+        let shader_source = match shader_maker.make_shader_with_values(source, shader_defs.clone())
+        {
+            Ok(shader_source) => shader_source,
+            Err(e) => {
+                println!("{}", shader_maker.diagnostic(&e));
+                return false;
+            }
+        };
+        compose_times.push(compose_start.elapsed());
+
+        // Pipeline creation, the draw and the readback.
+        let render_start = Instant::now();
+        if let Err(e) = render::render(gpu, shader_source, &options) {
+            println!("{e}");
+            return false;
+        }
+        render_times.push(render_start.elapsed());
+    }
+
+    let frames: Vec<Duration> = compose_times
+        .iter()
+        .zip(&render_times)
+        .map(|(compose, render)| *compose + *render)
+        .collect();
+    let total: Duration = frames.iter().sum();
+
+    println!(
+        "{iterations} frames at {}x{} in {:?} ({:?} including warm-up)",
+        options.size.0,
+        options.size.1,
+        total,
+        start.elapsed()
+    );
+    print_stats("compose", &compose_times);
+    print_stats("render", &render_times);
+    print_stats("frame", &frames);
+    println!(
+        "{:.1} frames per second",
+        iterations as f64 / total.as_secs_f64()
+    );
+
+    let mut shader_maker = ShaderMaker::non_validating();
+    shader_maker.set_caching(false);
+    let mut unvalidated_times = Vec::new();
+    for _ in 0..iterations {
+        let compose_start = Instant::now();
+        if let Err(e) = shader_maker.make_shader_with_values(source, shader_defs.clone()) {
+            println!("{}", shader_maker.diagnostic(&e));
+            return false;
+        }
+        unvalidated_times.push(compose_start.elapsed());
+    }
+    print_stats("unvalidated", &unvalidated_times);
+    println!(
+        "Skipping validation composes {:.2}x as fast",
+        mean(&compose_times).as_secs_f64() / mean(&unvalidated_times).as_secs_f64()
+    );
+
+    true
+}
@@ -0,0 +1,45 @@
+// Separable box blur for `chain`, run as `blur.wgsl:fs_blur_h` then `blur.wgsl:fs_blur_v`.
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    return out;
+}
+
+@group(0) @binding(1)
+var previous: texture_2d<f32>;
+@group(0) @binding(2)
+var previous_sampler: sampler;
+
+// Taps on each side of the center.
+const RADIUS: i32 = 4;
+
+fn blur(position: vec4<f32>, direction: vec2<f32>) -> vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(previous));
+    let center = position.xy * texel;
+
+    var sum = vec4<f32>(0.0);
+    for (var i = -RADIUS; i <= RADIUS; i++) {
+        sum += textureSample(previous, previous_sampler, center + f32(i) * direction * texel);
+    }
+    return sum / f32(2 * RADIUS + 1);
+}
+
+@fragment
+fn fs_blur_h(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.clip_position, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn fs_blur_v(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.clip_position, vec2<f32>(0.0, 1.0));
+}
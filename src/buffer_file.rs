@@ -0,0 +1,84 @@
+use std::path::Path;
+
+/// Read raw buffer contents from a file.
+///
+/// Files ending in `.hex` hold hex digits, optionally separated by whitespace, commas or
+/// `0x` prefixes, with `#` starting a comment. Anything else is taken as raw bytes.
+pub fn load(path: &Path) -> Result<Vec<u8>, String> {
+    if path.extension().is_some_and(|ext| ext == "hex") {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        parse_hex(&text).map_err(|e| format!("{}: {e}", path.display()))
+    } else {
+        std::fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))
+    }
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+        .flat_map(|word| word.strip_prefix("0x").unwrap_or(word).bytes())
+        .collect();
+
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits ({})", digits.len()));
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).unwrap_or_default();
+            u8::from_str_radix(pair, 16).map_err(|_| format!("invalid hex byte `{pair}`"))
+        })
+        .collect()
+}
+
+/// Size in bytes of the uniform or storage buffer bound at `group`/`binding`, if the
+/// module declares one there.
+pub fn binding_size(module: &naga::Module, group: u32, binding: u32) -> Option<u32> {
+    let (_, var) = module.global_variables.iter().find(|(_, var)| {
+        var.binding
+            .as_ref()
+            .is_some_and(|b| b.group == group && b.binding == binding)
+    })?;
+
+    if !matches!(
+        var.space,
+        naga::AddressSpace::Uniform | naga::AddressSpace::Storage { .. }
+    ) {
+        return None;
+    }
+
+    let mut layouter = naga::proc::Layouter::default();
+    layouter.update(module.to_ctx()).ok()?;
+
+    Some(layouter[var.ty].size)
+}
+
+/// Check buffer contents against the size the shader expects at `group`/`binding`.
+pub fn check_size(
+    module: &naga::Module,
+    group: u32,
+    binding: u32,
+    len: usize,
+) -> Result<(), String> {
+    let Some(expected) = binding_size(module, group, binding) else {
+        return Err(format!(
+            "shader has no uniform or storage buffer at @group({group}) @binding({binding})"
+        ));
+    };
+
+    if len < expected as usize {
+        return Err(format!(
+            "buffer is {len} bytes, but the shader expects {expected} at @group({group}) @binding({binding})"
+        ));
+    }
+
+    if len > expected as usize {
+        log::warn!("buffer is {len} bytes, only the first {expected} are read by the shader");
+    }
+
+    Ok(())
+}
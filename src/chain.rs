@@ -0,0 +1,190 @@
+use crate::render::{self, Gpu, RenderError, RenderOptions};
+
+/// One pass of a chain.
+pub struct PassDesc<'a> {
+    pub shader_source: wgpu::ShaderSource<'a>,
+    /// Fragment entry point drawing the pass.
+    pub entry_point: &'a str,
+    /// Bind the previous pass's output as `@group(0) @binding(1)`, with a sampler as
+    /// `@group(0) @binding(2)`, the same slots `scaffold --texture` generates.
+    pub reads_previous: bool,
+}
+
+/// Render `passes` in order, ping-ponging between two textures so every pass can sample
+/// the one before it, and read back the last pass's output.
+///
+/// All passes go into one submission. With `dump_intermediate`, the chain is submitted
+/// pass by pass instead, and the output of every pass is returned in order. Only `size`,
+/// `format`, `readback` and `vertex_entry` of the options apply.
+pub fn render_chain(
+    gpu: &Gpu,
+    passes: Vec<PassDesc>,
+    options: &RenderOptions,
+    dump_intermediate: bool,
+) -> Result<Vec<Vec<u8>>, RenderError> {
+    let device = &gpu.device;
+
+    if passes.is_empty() {
+        return Err(RenderError::EmptyChain);
+    }
+    if passes[0].reads_previous {
+        return Err(RenderError::NoPreviousPass);
+    }
+    for pass in &passes {
+        if let wgpu::ShaderSource::Naga(module) = &pass.shader_source {
+            render::check_entry_point(module, naga::ShaderStage::Vertex, options.vertex_entry)?;
+            render::check_entry_point(module, naga::ShaderStage::Fragment, pass.entry_point)?;
+        }
+    }
+
+    let u32_size = std::mem::size_of::<u32>() as u32;
+    options.readback.bytes_per_row(u32_size * options.size.0)?;
+
+    let targets = ["chain texture a", "chain texture b"].map(|label| {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: options.size.0,
+                height: options.size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: options.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    });
+    let views = targets
+        .each_ref()
+        .map(|target| target.create_view(&wgpu::TextureViewDescriptor::default()));
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+    let input_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("chain input bind group layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    });
+
+    // Bind group `i` samples target `i`, for the pass writing to the other one.
+    let input_bind_groups = views.each_ref().map(|view| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("chain input bind group"),
+            layout: &input_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        })
+    });
+
+    let layouts = [false, true].map(|reads_previous| {
+        let bind_group_layouts: &[&wgpu::BindGroupLayout] = if reads_previous {
+            &[&input_layout]
+        } else {
+            &[]
+        };
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        })
+    });
+
+    let (vertex_buffer, index_buffer) = render::quad_buffers(device);
+
+    let new_encoder = || {
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("chain render encoder"),
+        })
+    };
+    let mut encoder = new_encoder();
+    let mut outputs = Vec::new();
+    let last = passes.len() - 1;
+
+    for (index, pass) in passes.into_iter().enumerate() {
+        let pass_options = RenderOptions {
+            format: options.format,
+            vertex_entry: options.vertex_entry,
+            fragment_entry: pass.entry_point,
+            ..Default::default()
+        };
+        let pipeline = render::capture_validation(device, || {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: pass.shader_source,
+            });
+            render::quad_pipeline(
+                device,
+                &shader,
+                &layouts[pass.reads_previous as usize],
+                options.format,
+                &pass_options,
+            )
+        })?;
+
+        let target = index % 2;
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("chain pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &views[target],
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.set_pipeline(&pipeline);
+            if pass.reads_previous {
+                render_pass.set_bind_group(0, &input_bind_groups[1 - target], &[]);
+            }
+            render_pass.draw_indexed(0..6, 0, 0..1);
+        }
+
+        // Two passes on, this target is drawn over, so dumps are read back right away.
+        if dump_intermediate || index == last {
+            let submitted = std::mem::replace(&mut encoder, new_encoder());
+            outputs.push(render::read_back(
+                gpu,
+                submitted,
+                &targets[target],
+                options.readback,
+            )?);
+        }
+    }
+
+    Ok(outputs)
+}
@@ -0,0 +1,30 @@
+// Writes red to the first color target and green to the second, for checking that
+// renders with several color targets read back each of them.
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+struct FragmentOutput {
+    @location(0) first: vec4<f32>,
+    @location(1) second: vec4<f32>,
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    var out: FragmentOutput;
+    out.first = vec4<f32>(1.0, 0.0, 0.0, 1.0);
+    out.second = vec4<f32>(0.0, 1.0, 0.0, 1.0);
+    return out;
+}
@@ -0,0 +1,135 @@
+use crate::render::{self, Gpu, RenderError};
+use wgpu::util::DeviceExt;
+
+/// Parse a workgroup count like `4`, `4,4` or `4,4,1`, missing dimensions default to 1.
+pub fn parse_dispatch(arg: &str) -> Result<[u32; 3], String> {
+    parse_counts(arg, "workgroup")
+}
+
+/// Parse an invocation count like `parse_dispatch` does a workgroup count.
+pub fn parse_invocations(arg: &str) -> Result<[u32; 3], String> {
+    parse_counts(arg, "invocation")
+}
+
+fn parse_counts(arg: &str, what: &str) -> Result<[u32; 3], String> {
+    let counts = arg
+        .split(',')
+        .map(|count| {
+            count
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| format!("invalid {what} count `{count}`"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match counts[..] {
+        [x] => Ok([x, 1, 1]),
+        [x, y] => Ok([x, y, 1]),
+        [x, y, z] => Ok([x, y, z]),
+        _ => Err(format!("expected up to three {what} counts, got `{arg}`")),
+    }
+}
+
+/// Workgroups to dispatch for at least `total` invocations of `entry_point`, rounding up
+/// to whole workgroups of its `@workgroup_size` so no element is left out.
+pub fn dispatch_for(entry_point: &naga::EntryPoint, total: [u32; 3]) -> [u32; 3] {
+    let mut dispatch = [0; 3];
+    for ((count, total), size) in dispatch
+        .iter_mut()
+        .zip(total)
+        .zip(entry_point.workgroup_size)
+    {
+        *count = total.div_ceil(size.max(1));
+    }
+    dispatch
+}
+
+/// The compute entry point of this name, `RenderError::MissingEntryPoint` if there is none.
+pub fn entry_point<'a>(
+    module: &'a naga::Module,
+    name: &str,
+) -> Result<&'a naga::EntryPoint, RenderError> {
+    render::check_entry_point(module, naga::ShaderStage::Compute, name)?;
+    Ok(module
+        .entry_points
+        .iter()
+        .find(|entry_point| {
+            entry_point.stage == naga::ShaderStage::Compute && entry_point.name == name
+        })
+        .expect("the entry point was checked to exist"))
+}
+
+/// Run the compute entry point `entry_point` over `dispatch` workgroups and return the
+/// contents of the storage buffer at `@group(0) @binding(0)` afterwards.
+///
+/// The buffer starts out as `storage`, which is padded with zeros to a multiple of four
+/// bytes for the copy. The returned bytes have the length of `storage` again.
+pub fn run_compute(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    entry_point: &str,
+    dispatch: [u32; 3],
+    storage: &[u8],
+) -> Result<Vec<u8>, RenderError> {
+    let device = &gpu.device;
+
+    let pipeline = render::capture_validation(device, || {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("compute shader"),
+            source: shader_source,
+        });
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("compute pipeline"),
+            layout: None,
+            module: &shader,
+            entry_point,
+        })
+    })?;
+
+    let mut contents = storage.to_vec();
+    contents.resize(
+        render::align_up(storage.len() as u32, wgpu::COPY_BUFFER_ALIGNMENT as u32) as usize,
+        0,
+    );
+
+    let storage_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("storage buffer"),
+        contents: &contents,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("compute bind group"),
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: storage_buffer.as_entire_binding(),
+        }],
+    });
+
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("compute pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(dispatch[0], dispatch[1], dispatch[2]);
+    }
+
+    let mut data = render::submit_buffer_read_back(gpu, encoder, &storage_buffer).wait(device)?;
+    data.truncate(storage.len());
+
+    Ok(data)
+}
+
+/// Print bytes as hex, 16 to a line, prefixed with the offset of the first.
+pub fn print_hex(data: &[u8]) {
+    for (line, chunk) in data.chunks(16).enumerate() {
+        let bytes: Vec<String> = chunk.iter().map(|byte| format!("{byte:02x}")).collect();
+        println!("{:08x}  {}", line * 16, bytes.join(" "));
+    }
+}
@@ -0,0 +1,23 @@
+// Writes the pixel's own coordinates, modulo 256, to red and green so a readback can be
+// checked pixel by pixel.
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<u32>(in.clip_position.xy) % 256u;
+    return vec4<f32>(vec2<f32>(pixel) / 255.0, 0.0, 1.0);
+}
@@ -0,0 +1,39 @@
+// Lit cube for `--geometry cube --depth`, turned so three faces are visible.
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+}
+
+fn rotate(v: vec3<f32>) -> vec3<f32> {
+    // 30 degrees around x, then 40 degrees around y.
+    let a = radians(30.0);
+    let b = radians(40.0);
+    let x = vec3<f32>(v.x, v.y * cos(a) - v.z * sin(a), v.y * sin(a) + v.z * cos(a));
+    return vec3<f32>(x.x * cos(b) + x.z * sin(b), x.y, -x.x * sin(b) + x.z * cos(b));
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    let position = rotate(model.position);
+    // Orthographic, depth from 0 in front to 1 behind.
+    out.clip_position = vec4<f32>(position.xy, 0.5 - position.z * 0.5, 1.0);
+    out.normal = rotate(model.normal);
+    out.uv = model.uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let light = max(dot(normalize(in.normal), normalize(vec3<f32>(0.3, 0.5, 1.0))), 0.0);
+    let checker = f32((u32(in.uv.x * 4.0) + u32(in.uv.y * 4.0)) % 2u);
+    return vec4<f32>(vec3<f32>(0.2 + 0.8 * light) * (0.6 + 0.4 * checker), 1.0);
+}
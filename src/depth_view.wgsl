@@ -0,0 +1,26 @@
+// Copies a depth attachment into a color target as gray, for renders without a fragment stage.
+
+// Bound as a float texture, since GLSL can't load from depth textures.
+@group(0) @binding(0)
+var depth: texture_2d<f32>;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let value = textureLoad(depth, vec2<i32>(in.clip_position.xy), 0).r;
+    return vec4<f32>(value, value, value, 1.0);
+}
@@ -0,0 +1,84 @@
+use image::{ImageBuffer, Rgba, RgbaImage};
+use std::path::Path;
+
+/// Per-channel absolute difference of two images of the same size, multiplied by
+/// `amplify` and saturated, with alpha forced opaque so the result is always visible.
+pub fn difference(a: &RgbaImage, b: &RgbaImage, amplify: u8) -> Result<RgbaImage, String> {
+    if a.dimensions() != b.dimensions() {
+        return Err(format!(
+            "image sizes differ: {:?} vs {:?}",
+            a.dimensions(),
+            b.dimensions()
+        ));
+    }
+
+    let (width, height) = a.dimensions();
+
+    Ok(ImageBuffer::from_fn(width, height, |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        let channel = |i: usize| pa[i].abs_diff(pb[i]).saturating_mul(amplify);
+        // Fold an alpha difference into the colour channels, it would be lost otherwise.
+        let alpha = channel(3);
+
+        Rgba([
+            channel(0).max(alpha),
+            channel(1).max(alpha),
+            channel(2).max(alpha),
+            255,
+        ])
+    }))
+}
+
+/// Mean absolute difference of each RGBA channel of two images of the same size.
+pub fn mean_absolute_error(a: &RgbaImage, b: &RgbaImage) -> [f64; 4] {
+    let mut sums = [0u64; 4];
+    for (pa, pb) in a.pixels().zip(b.pixels()) {
+        for (sum, (ca, cb)) in sums.iter_mut().zip(pa.0.into_iter().zip(pb.0)) {
+            *sum += ca.abs_diff(cb) as u64;
+        }
+    }
+
+    let count = a.pixels().len().max(1) as f64;
+    sums.map(|sum| sum as f64 / count)
+}
+
+/// Compare two images on disk, e.g. a render and its golden, and save their difference.
+///
+/// Returns `Ok(false)` if any pixel differs.
+pub fn run(a: &Path, b: &Path, output: &Path, amplify: u8) -> Result<bool, String> {
+    let open = |path: &Path| {
+        image::open(path)
+            .map(|image| image.to_rgba8())
+            .map_err(|e| format!("failed to open {}: {e}", path.display()))
+    };
+
+    compare(&open(a)?, &open(b)?, output, amplify)
+}
+
+/// Save the difference of two images and print how much they differ.
+///
+/// Returns `Ok(false)` if any pixel differs.
+pub fn compare(a: &RgbaImage, b: &RgbaImage, output: &Path, amplify: u8) -> Result<bool, String> {
+    let diff = difference(a, b, amplify)?;
+
+    let differing = a.pixels().zip(b.pixels()).filter(|(a, b)| a != b).count();
+    let max = a
+        .pixels()
+        .zip(b.pixels())
+        .flat_map(|(a, b)| a.0.into_iter().zip(b.0).map(|(a, b)| a.abs_diff(b)))
+        .max()
+        .unwrap_or_default();
+
+    diff.save(output)
+        .map_err(|e| format!("failed to save {}: {e}", output.display()))?;
+
+    let [r, g, b, alpha] = mean_absolute_error(a, b);
+    println!(
+        "{differing} of {} pixels differ, max channel difference {max}",
+        a.pixels().len()
+    );
+    println!("Mean absolute error: R {r:.3}, G {g:.3}, B {b:.3}, A {alpha:.3}");
+    println!("Difference image written to {}", output.display());
+
+    Ok(differing == 0)
+}
@@ -0,0 +1,24 @@
+// Draws a triangle covering the whole target from the vertex index alone, without any
+// vertex buffer, and shows the uvs, (0, 0) at the top left to (1, 1) at the bottom right,
+// as red and green. Render it with `--geometry fullscreen`.
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // The uvs (0, 0), (2, 0) and (0, 2), the target is the half of the triangle up to 1.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.uv, 0.0, 1.0);
+}
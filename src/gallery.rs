@@ -0,0 +1,177 @@
+use crate::output::{self, ImageMetadata};
+use crate::render::{self, Gpu, RenderOptions};
+use crate::shader_maker::ShaderMaker;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Thumbnail edge length in pixels.
+const THUMBNAIL_SIZE: u32 = 128;
+
+/// Color of the placeholder used for shaders that fail to compile or render.
+const PLACEHOLDER_COLOR: [u8; 4] = [255, 0, 0, 255];
+
+struct Entry {
+    name: String,
+    pixels: Vec<u8>,
+    error: Option<String>,
+}
+
+/// Render every `.wgsl` file in `dir` to a thumbnail under `output_dir`, then assemble the
+/// thumbnails into `index.png` and list them, together with any errors, in `index.html`.
+///
+/// Returns `false` if any shader failed.
+pub fn run(gpu: &Gpu, dir: &Path, output_dir: &Path) -> std::io::Result<bool> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wgsl"))
+        .collect();
+    paths.sort();
+
+    std::fs::create_dir_all(output_dir)?;
+
+    let options = RenderOptions {
+        size: (THUMBNAIL_SIZE, THUMBNAIL_SIZE),
+        ..Default::default()
+    };
+
+    let mut entries = Vec::new();
+
+    for path in &paths {
+        let name = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+
+        let result = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| {
+                // A fresh maker per shader so one file can't affect the next.
+                let mut shader_maker = ShaderMaker::new();
+                shader_maker
+                    .make_shader_with_path(&source, &path.to_string_lossy(), HashMap::new())
+                    .map_err(|e| shader_maker.diagnostic(&e))
+            })
+            .and_then(|shader_source| {
+                render::render(gpu, shader_source, &options).map_err(|e| e.to_string())
+            });
+
+        let entry = match result {
+            Ok(pixels) => {
+                println!("[ok]   {}", path.display());
+                Entry {
+                    name,
+                    pixels,
+                    error: None,
+                }
+            }
+            Err(e) => {
+                println!("[fail] {}", path.display());
+                Entry {
+                    name,
+                    pixels: PLACEHOLDER_COLOR.repeat((THUMBNAIL_SIZE * THUMBNAIL_SIZE) as usize),
+                    error: Some(strip_ansi(&e)),
+                }
+            }
+        };
+
+        let metadata = ImageMetadata::new(&path.to_string_lossy(), &[]);
+        output::save_png_with_metadata(
+            &output_dir.join(format!("{}.png", entry.name)),
+            THUMBNAIL_SIZE,
+            THUMBNAIL_SIZE,
+            &entry.pixels,
+            &metadata,
+        )
+        .map_err(std::io::Error::other)?;
+
+        entries.push(entry);
+    }
+
+    write_atlas(&entries, dir, &output_dir.join("index.png"))?;
+    write_html(&entries, &output_dir.join("index.html"))?;
+
+    println!(
+        "Wrote {} thumbnails to {}",
+        entries.len(),
+        output_dir.display()
+    );
+
+    Ok(entries.iter().all(|entry| entry.error.is_none()))
+}
+
+/// Lay the thumbnails out on a roughly square grid.
+fn write_atlas(entries: &[Entry], dir: &Path, path: &Path) -> std::io::Result<()> {
+    let count = entries.len().max(1) as u32;
+    let columns = (count as f32).sqrt().ceil() as u32;
+    let rows = count.div_ceil(columns);
+
+    let width = columns * THUMBNAIL_SIZE;
+    let height = rows * THUMBNAIL_SIZE;
+    let row_bytes = (THUMBNAIL_SIZE * 4) as usize;
+
+    let mut atlas = vec![0u8; (width * height * 4) as usize];
+
+    for (index, entry) in entries.iter().enumerate() {
+        let column = index as u32 % columns;
+        let row = index as u32 / columns;
+
+        for y in 0..THUMBNAIL_SIZE {
+            let src = y as usize * row_bytes;
+            let dst = (((row * THUMBNAIL_SIZE + y) * width + column * THUMBNAIL_SIZE) * 4) as usize;
+            atlas[dst..dst + row_bytes].copy_from_slice(&entry.pixels[src..src + row_bytes]);
+        }
+    }
+
+    let metadata = ImageMetadata::new(&dir.to_string_lossy(), &[]);
+    output::save_png_with_metadata(path, width, height, &atlas, &metadata)
+        .map_err(std::io::Error::other)
+}
+
+fn write_html(entries: &[Entry], path: &Path) -> std::io::Result<()> {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Shader gallery</title></head>\n<body>\n",
+    );
+
+    for entry in entries {
+        let name = escape_html(&entry.name);
+        html.push_str(&format!(
+            "<figure>\n<img src=\"{name}.png\" width=\"{THUMBNAIL_SIZE}\" height=\"{THUMBNAIL_SIZE}\">\n<figcaption>{name}</figcaption>\n"
+        ));
+        if let Some(error) = &entry.error {
+            html.push_str(&format!("<pre>{}</pre>\n", escape_html(error)));
+        }
+        html.push_str("</figure>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+
+    std::fs::write(path, html)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Remove the terminal color codes naga_oil puts into its diagnostics.
+fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip the CSI sequence up to and including its final letter.
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+
+    stripped
+}
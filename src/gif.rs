@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Colors of the palette every frame is reduced to, the most GIF allows.
+const PALETTE_SIZE: usize = 256;
+/// Bits of the color indices, the LZW codes start one wider.
+const MIN_CODE_SIZE: u8 = 8;
+const CLEAR_CODE: u16 = 1 << MIN_CODE_SIZE;
+const END_CODE: u16 = CLEAR_CODE + 1;
+/// Codes are at most 12 bits, the table starts over once it is full.
+const MAX_CODES: u16 = 1 << 12;
+
+/// Writes an endlessly looping animated GIF frame by frame, without holding on to more
+/// than the frame being written.
+///
+/// Every frame is reduced to a palette of its own with NeuQuant, which GIF's 256 colors
+/// per frame make necessary, and is opaque.
+pub struct GifWriter<W: Write> {
+    writer: W,
+    size: (u16, u16),
+    /// Time every frame is shown for, in hundredths of a second.
+    delay: u16,
+}
+
+impl<W: Write> GifWriter<W> {
+    /// Write the header of a `size` animation whose frames are shown for `delay` hundredths
+    /// of a second each.
+    pub fn new(mut writer: W, size: (u32, u32), delay: u16) -> io::Result<Self> {
+        let size = match (u16::try_from(size.0), u16::try_from(size.1)) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "GIFs are at most 65535 pixels across, got {}x{}",
+                        size.0, size.1
+                    ),
+                ))
+            }
+        };
+
+        writer.write_all(b"GIF89a")?;
+        // Logical screen without a global color table, every frame brings its own.
+        writer.write_all(&size.0.to_le_bytes())?;
+        writer.write_all(&size.1.to_le_bytes())?;
+        writer.write_all(&[0, 0, 0])?;
+        // The NETSCAPE2.0 application extension, looping forever.
+        writer.write_all(&[0x21, 0xff, 11])?;
+        writer.write_all(b"NETSCAPE2.0")?;
+        writer.write_all(&[3, 1, 0, 0, 0])?;
+
+        Ok(Self {
+            writer,
+            size,
+            delay,
+        })
+    }
+
+    /// Quantize and write a frame of tightly packed RGBA8 pixels, alpha being ignored.
+    pub fn write_frame(&mut self, rgba: &[u8]) -> io::Result<()> {
+        let (width, height) = self.size;
+        assert_eq!(
+            rgba.len(),
+            4 * width as usize * height as usize,
+            "frames are tightly packed RGBA8 of the animation's size"
+        );
+
+        let quantizer = color_quant::NeuQuant::new(10, PALETTE_SIZE, rgba);
+        let indices: Vec<u8> = rgba
+            .chunks_exact(4)
+            .map(|pixel| quantizer.index_of(pixel) as u8)
+            .collect();
+        let mut palette = quantizer.color_map_rgb();
+        palette.resize(3 * PALETTE_SIZE, 0);
+
+        // Graphic control extension, with the delay and nothing transparent.
+        self.writer.write_all(&[0x21, 0xf9, 4, 0])?;
+        self.writer.write_all(&self.delay.to_le_bytes())?;
+        self.writer.write_all(&[0, 0])?;
+
+        // Image descriptor covering the whole screen, with a local color table of 2^8 entries.
+        self.writer.write_all(&[0x2c, 0, 0, 0, 0])?;
+        self.writer.write_all(&width.to_le_bytes())?;
+        self.writer.write_all(&height.to_le_bytes())?;
+        self.writer.write_all(&[0x80 | (MIN_CODE_SIZE - 1)])?;
+        self.writer.write_all(&palette)?;
+
+        self.writer.write_all(&[MIN_CODE_SIZE])?;
+        for block in lzw_compress(&indices).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0])
+    }
+
+    /// Write the trailer and hand back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&[0x3b])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Packs codes of varying width into bytes, least significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/// GIF's variant of LZW compression of 8-bit color indices.
+fn lzw_compress(indices: &[u8]) -> Vec<u8> {
+    let mut output = BitWriter::default();
+    // The code of every string seen so far, as the code of all but its last index and that index.
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = END_CODE + 1;
+    let mut code_size = MIN_CODE_SIZE + 1;
+
+    output.write(CLEAR_CODE, code_size);
+
+    let Some((&first, rest)) = indices.split_first() else {
+        output.write(END_CODE, code_size);
+        return output.finish();
+    };
+    let mut prefix = first as u16;
+
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+
+        output.write(prefix, code_size);
+        if next_code < MAX_CODES {
+            // The decoder widens its codes as soon as this one no longer fits.
+            if next_code == 1 << code_size {
+                code_size += 1;
+            }
+            table.insert((prefix, index), next_code);
+            next_code += 1;
+        } else {
+            output.write(CLEAR_CODE, code_size);
+            table.clear();
+            next_code = END_CODE + 1;
+            code_size = MIN_CODE_SIZE + 1;
+        }
+        prefix = index as u16;
+    }
+
+    output.write(prefix, code_size);
+    output.write(END_CODE, code_size);
+    output.finish()
+}
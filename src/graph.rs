@@ -0,0 +1,72 @@
+use naga_oil::compose::get_preprocessor_data;
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The registered module an import refers to, either by its full path or as the
+/// module part of an item import like `my::module::item`.
+pub(crate) fn resolve<'a>(import: &str, modules: &'a [(String, String)]) -> Option<&'a str> {
+    modules
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|name| {
+            import == *name
+                || import
+                    .strip_prefix(*name)
+                    .is_some_and(|rest| rest.starts_with("::"))
+        })
+        // The longest match wins, so `a::b` beats `a` for `a::b::item`.
+        .max_by_key(|name| name.len())
+}
+
+/// Graphviz source for the import graph of `shader` and the composable modules, given as
+/// `(name, source)` pairs, with an edge from every importer to what it imports.
+///
+/// Imports are taken from naga_oil's preprocessor, which leaves out those nothing uses.
+/// The shader is drawn as a box, imports that don't match a registered module are dashed.
+pub fn dot(shader_name: &str, shader: &str, modules: &[(String, String)]) -> String {
+    let mut dot = String::from("digraph imports {\n    rankdir=LR;\n");
+    dot.push_str(&format!("    {} [shape=box];\n", quote(shader_name)));
+    for (name, _) in modules {
+        dot.push_str(&format!("    {};\n", quote(name)));
+    }
+
+    let importers = std::iter::once((shader_name, shader)).chain(
+        modules
+            .iter()
+            .map(|(name, source)| (name.as_str(), source.as_str())),
+    );
+
+    let mut missing = Vec::new();
+
+    for (importer, source) in importers {
+        let (_, imports, _) = get_preprocessor_data(source);
+
+        let mut targets: Vec<String> = imports
+            .iter()
+            .map(|import| match resolve(&import.import, modules) {
+                Some(name) => name.to_owned(),
+                None => {
+                    missing.push(import.import.clone());
+                    import.import.clone()
+                }
+            })
+            .collect();
+        targets.sort();
+        targets.dedup();
+
+        for target in targets {
+            dot.push_str(&format!("    {} -> {};\n", quote(importer), quote(&target)));
+        }
+    }
+
+    missing.sort();
+    missing.dedup();
+    for name in missing {
+        dot.push_str(&format!("    {} [style=dashed];\n", quote(&name)));
+    }
+
+    dot.push_str("}\n");
+    dot
+}
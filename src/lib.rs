@@ -0,0 +1,21 @@
+//! Preprocess and compose WGSL with naga_oil through `ShaderMaker`, and render the result
+//! offscreen with wgpu through the helpers in `render`.
+
+pub mod animation;
+pub mod chain;
+pub mod compute;
+pub mod gif;
+pub mod graph;
+pub mod obj;
+pub mod output;
+pub mod reflect;
+pub mod render;
+pub mod shader_maker;
+pub mod stencil;
+pub mod texture;
+pub mod uniforms;
+
+pub use render::{
+    Gpu, GpuOptions, Mesh, RenderError, RenderOptions, Vertex2d, Vertex3d, VertexBuffer,
+};
+pub use shader_maker::{ShaderError, ShaderMaker};
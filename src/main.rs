@@ -1,338 +1,1734 @@
-use naga_oil::compose::{
-    ComposableModuleDescriptor, Composer, NagaModuleDescriptor, ShaderDefValue,
+use clap::{Parser, Subcommand};
+use naga_oil::compose::ShaderDefValue;
+use naga_oil_demo::{
+    animation, chain, compute, gif, graph, obj, output, reflect, render, shader_maker, stencil,
+    texture, uniforms,
 };
-use std::borrow::Cow;
+use shader_maker::{ShaderKind, ShaderMaker};
 use std::collections::HashMap;
-use wgpu;
-use wgpu::util::DeviceExt;
-use wgpu::{BufferAddress, Extent3d, ImageCopyTexture, StoreOp};
+use std::path::PathBuf;
 
-struct ShaderMaker {
-    composer: Composer,
+mod adapters;
+mod batch;
+mod bench;
+mod buffer_file;
+mod diff;
+mod gallery;
+#[cfg(feature = "matrix")]
+mod matrix;
+#[cfg(feature = "preview")]
+mod preview;
+mod requirements;
+mod scaffold;
+mod source;
+mod specialize;
+mod sweep;
+mod transpile;
+#[cfg(feature = "watch")]
+mod watch;
+
+#[derive(Parser)]
+#[command(about = "Preprocess WGSL with naga_oil and render it offscreen")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Don't validate the composed shader before handing it to wgpu (unsafe).
+    #[arg(long)]
+    skip_validation: bool,
+    /// Shader def as `NAME` or `NAME=VALUE` (bool, int or uint), can be repeated.
+    #[arg(short, long = "def", value_parser = shader_maker::parse_def)]
+    defs: Vec<(String, ShaderDefValue)>,
+    /// Shader def as `NAME=VALUE` that is always a signed int, can be repeated.
+    #[arg(long = "def-int", value_parser = shader_maker::parse_def_int)]
+    int_defs: Vec<(String, ShaderDefValue)>,
+    /// `.toml` or `.json` file of defs for the shader and its modules, which `--def` and
+    /// `--def-int` override.
+    #[arg(long)]
+    defs_file: Option<PathBuf>,
+    /// Render one variant per value of a def, e.g. `QUALITY=0..4`, can be repeated.
+    #[arg(long = "def-sweep", value_parser = sweep::parse_sweep)]
+    def_sweeps: Vec<sweep::DefSweep>,
+    /// Grayscale image, the shader is only drawn where it is brighter than 50%.
+    #[arg(long)]
+    stencil_mask: Option<PathBuf>,
+    /// Composable module to register before compiling, can be repeated.
+    #[arg(long = "module")]
+    modules: Vec<String>,
+    /// Directory whose `.wgsl` files are all registered as composable modules, can be repeated.
+    #[arg(long = "module-dir")]
+    module_dirs: Vec<PathBuf>,
+    /// File of plain WGSL put ahead of the shader's source, e.g. shared constants, can be
+    /// repeated. The shader's line numbers shift by its length.
+    #[arg(long = "prepend")]
+    prepends: Vec<String>,
+    /// Print the registered modules and the defs they were added with before compiling.
+    #[arg(long)]
+    list_modules: bool,
+    /// Report the approximate memory used by registered and composed modules.
+    #[arg(long)]
+    profile_memory: bool,
+    /// Path or `http(s)://` URL of the WGSL or GLSL fragment shader to render, `test.wgsl`
+    /// if not given.
+    #[arg(long)]
+    shader: Option<String>,
+    /// Language and stage of `--shader`, inferred from `.wgsl` and `.frag`.
+    #[arg(long, value_enum)]
+    shader_type: Option<ShaderKind>,
+    /// WGSL or GLSL (`.vert`) shader with the vertex stage, needed for GLSL fragment shaders.
+    /// It is composed with the same defs and modules.
+    #[arg(long)]
+    vertex_shader: Option<String>,
+    /// Raw contents of the uniform buffer at `@group(0) @binding(0)`, as binary or a `.hex` file.
+    #[arg(long)]
+    uniform_file: Option<PathBuf>,
+    /// Bind time and resolution at `@group(0) @binding(0)`, see `src/animated.wgsl`.
+    #[arg(long, conflicts_with = "uniform_file")]
+    frame_uniforms: bool,
+    /// Float uniform as NAME=VALUE, can be repeated. They are bound at `@group(0) @binding(0)`
+    /// as the `f32` members of the same names of the shader's struct there, see
+    /// `src/uniforms.wgsl`.
+    #[arg(
+        long = "uniform",
+        value_parser = uniforms::parse,
+        conflicts_with_all = ["uniform_file", "frame_uniforms"]
+    )]
+    uniforms: Vec<(String, f32)>,
+    /// Time in seconds passed in the frame uniforms of a one-shot render, or of the first
+    /// of `--frames`.
+    #[arg(long, default_value_t = 0.0, requires = "frame_uniforms")]
+    time: f32,
+    /// Render this many frames instead of one, the time advancing by `1 / --fps` seconds
+    /// every frame. Each is saved with a `_0000`, `_0001`, ... suffix, or all as one
+    /// animation to a `.gif` output.
+    ///
+    /// Frames are drawn on the plain quad, so the options of still renders that change
+    /// what is drawn or how can't be combined with it.
+    #[arg(
+        long,
+        requires = "frame_uniforms",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = [
+            "samples", "depth", "geometry", "mesh", "stencil_mask", "input_textures",
+            "uniforms", "push_constant_file", "layers", "cubemap", "color_targets",
+            "def_sweeps", "vertex_shader", "profile_memory", "profile", "poll_readback",
+        ]
+    )]
+    frames: Option<u32>,
+    /// Frames per second of `--frames`, GIFs round the time per frame to 1/100 s.
+    #[arg(long, default_value_t = 30.0, requires = "frames")]
+    fps: f32,
+    /// Raw push constant data for the vertex and fragment stages, as binary or a `.hex` file.
+    #[arg(long)]
+    push_constant_file: Option<PathBuf>,
+    /// Image sampled at `@group(0) @binding(1)`, repeat it for the slices of a 3D texture.
+    #[arg(long = "input-texture")]
+    input_textures: Vec<PathBuf>,
+    /// Dimension of the input texture.
+    #[arg(long, value_enum, default_value_t)]
+    texture_dimension: texture::Dimension,
+    /// Whether the input texture holds linear values or sRGB encoded colours.
+    #[arg(long, value_enum, default_value_t)]
+    input_color_space: texture::ColorSpace,
+    /// How the input texture's sampler filters between texels.
+    #[arg(long, value_enum, default_value_t, requires = "input_textures")]
+    sampler_filter: texture::Filter,
+    /// What the input texture's sampler returns outside of the texture.
+    #[arg(long, value_enum, default_value_t, requires = "input_textures")]
+    sampler_address_mode: texture::AddressMode,
+    /// Render target size as `WIDTHxHEIGHT`.
+    #[arg(long, default_value = "128x128", value_parser = render::parse_size)]
+    size: (u32, u32),
+    /// Render into this many layers of an array texture, the layer index being the
+    /// instance index. Each is saved with a `_layer{N}` suffix.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    layers: u32,
+    /// Render the six faces of a cubemap as layers, in +X, -X, +Y, -Y, +Z, -Z order.
+    #[arg(long, conflicts_with = "layers")]
+    cubemap: bool,
+    /// Color targets the fragment shader writes, one for every `@location` of its output.
+    /// With more than one, each is saved with a `_{N}` suffix.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=8))]
+    color_targets: u32,
+    /// Vertex entry point drawing the quad.
+    #[arg(long, default_value = "vs_main")]
+    vertex_entry: String,
+    /// Fragment entry point drawing the quad.
+    #[arg(long, default_value = "fs_main")]
+    fragment_entry: String,
+    /// Mesh to draw, the 3D ones have normals and uvs at locations 1 and 2.
+    #[arg(long, value_enum, default_value_t)]
+    geometry: render::Geometry,
+    /// Wavefront OBJ file to draw instead of a built-in mesh, laid out like `cube`.
+    #[arg(long, conflicts_with = "geometry")]
+    mesh: Option<PathBuf>,
+    /// Index format of `--mesh`, `u16` halves the index buffer of meshes of up to 65536 vertices.
+    #[arg(long, value_enum, default_value_t, requires = "mesh")]
+    index_format: render::IndexFormat,
+    /// Attach a depth buffer, cleared to 1.0, and draw with a `less` depth test.
+    #[arg(long)]
+    depth: bool,
+    /// Draw without a fragment stage and save the depth as gray, which shaders without a
+    /// fragment entry point always are.
+    #[arg(long, requires = "depth")]
+    depth_only: bool,
+    /// Color to clear the target to as `R,G,B,A`, `0.1,0.2,0.3,1` if not given.
+    #[arg(long, value_parser = render::parse_color)]
+    clear: Option<wgpu::Color>,
+    /// Draw over the target's initial contents, transparent black, instead of clearing it.
+    #[arg(long, conflicts_with = "clear")]
+    no_clear: bool,
+    /// How the shader's output is combined with the background.
+    #[arg(long, value_enum, default_value_t)]
+    blend: render::Blend,
+    /// Blend constant as `R,G,B,A` for `--blend constant`.
+    #[arg(long, value_parser = render::parse_color)]
+    blend_constant: Option<wgpu::Color>,
+    /// Samples per pixel for MSAA, 1 if the format doesn't support the count.
+    #[arg(long, default_value_t = 1)]
+    samples: u32,
+    /// Render target format, written as 8 bits per channel except to `.exr`.
+    #[arg(long, value_enum, default_value_t)]
+    format: render::ImageFormat,
+    /// sRGB encode what linear targets hold when saving 8 bits per channel, so the image
+    /// looks the same as from an sRGB target. Image viewers assume sRGB.
+    #[arg(long)]
+    encode_srgb: bool,
+    /// Time the render pass on the GPU with timestamp queries and print it.
+    #[arg(long)]
+    profile: bool,
+    /// Await the readback on an executor, woken once a thread polling the device finishes
+    /// the copy, instead of blocking on the device.
+    #[arg(long)]
+    poll_readback: bool,
+    /// Fail a render whose readback takes longer than this many seconds instead of waiting
+    /// for a hung GPU forever.
+    #[arg(long, value_parser = render::parse_seconds, conflicts_with = "poll_readback")]
+    timeout: Option<std::time::Duration>,
+    /// Where to write the image, as .png, .jpg, .bmp or .exr by extension. Sweep variants get
+    /// their defs appended to the file stem.
+    #[arg(short, long, default_value = "image.png")]
+    output: PathBuf,
+    /// Open a window drawing the shader every frame instead of writing a PNG.
+    #[cfg(feature = "preview")]
+    #[arg(long, conflicts_with = "frames")]
+    preview: bool,
+    /// Leave the timestamp out of the PNG so identical renders are byte-identical.
+    #[arg(long)]
+    deterministic: bool,
+    /// Print the adapter's features, limits and downlevel capabilities instead of rendering.
+    #[arg(long, visible_alias = "dump-features")]
+    info: bool,
+    /// Graphics API to request the adapter on, any other is used if it has none.
+    #[arg(long, value_enum, global = true)]
+    backend: Option<render::Backend>,
+    /// Prefer a low power or a high performance GPU.
+    #[arg(long, value_enum, global = true)]
+    power: Option<render::Power>,
+}
+
+impl Cli {
+    fn gpu_options(&self) -> render::GpuOptions {
+        render::GpuOptions {
+            backend: self.backend,
+            power: self.power,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compose a shader for every available adapter and compare the emitted SPIR-V.
+    Adapters {
+        /// Path or `http(s)://` URL of the WGSL shader.
+        shader: String,
+        /// Shader def as `NAME` or `NAME=VALUE` (bool, int or uint), can be repeated.
+        #[arg(short, long = "def", value_parser = shader_maker::parse_def)]
+        defs: Vec<(String, ShaderDefValue)>,
+    },
+    /// Compose and validate every `.wgsl` file below a directory and report which failed.
+    Batch {
+        /// Directory of shaders, searched recursively.
+        dir: PathBuf,
+        /// Shader def as `NAME` or `NAME=VALUE` (bool, int or uint), can be repeated.
+        #[arg(short, long = "def", value_parser = shader_maker::parse_def)]
+        defs: Vec<(String, ShaderDefValue)>,
+        /// Composable module to register before compiling, can be repeated.
+        #[arg(short, long = "module")]
+        modules: Vec<String>,
+        /// Directory whose `.wgsl` files are all registered as composable modules, can be repeated.
+        #[arg(long = "module-dir")]
+        module_dirs: Vec<PathBuf>,
+        /// Threads to compose on, as many as the machine runs in parallel if not given.
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Measure end-to-end render throughput: composition, pipeline, draw and readback.
+    Bench {
+        /// Path or `http(s)://` URL of the WGSL shader, `test.wgsl` if not given.
+        shader: Option<String>,
+        /// Shader def as `NAME` or `NAME=VALUE` (bool, int or uint), can be repeated.
+        #[arg(short, long = "def", value_parser = shader_maker::parse_def)]
+        defs: Vec<(String, ShaderDefValue)>,
+        /// Number of frames to time.
+        #[arg(short = 'n', long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+        iterations: u32,
+    },
+    /// Print the naga capabilities a shader needs to validate.
+    Capabilities {
+        /// Path or `http(s)://` URL of the WGSL or GLSL shader.
+        shader: String,
+        /// Shader def as `NAME` or `NAME=VALUE` (bool, int or uint), can be repeated.
+        #[arg(short, long = "def", value_parser = shader_maker::parse_def)]
+        defs: Vec<(String, ShaderDefValue)>,
+        /// Language and stage of the shader, inferred from `.wgsl`, `.vert` and `.frag`.
+        #[arg(long, value_enum)]
+        shader_type: Option<ShaderKind>,
+    },
+    /// Run a compute shader and print the storage buffer at `@group(0) @binding(0)` afterwards.
+    Compute {
+        /// Path or `http(s)://` URL of the WGSL shader.
+        shader: String,
+        /// Shader def as `NAME` or `NAME=VALUE` (bool, int or uint), can be repeated.
+        #[arg(short, long = "def", value_parser = shader_maker::parse_def)]
+        defs: Vec<(String, ShaderDefValue)>,
+        /// Composable module to register before compiling, can be repeated.
+        #[arg(short, long = "module")]
+        modules: Vec<String>,
+        /// Name of the `@compute` function to run.
+        #[arg(long, default_value = "main")]
+        entry_point: String,
+        /// Workgroups to dispatch as `X[,Y[,Z]]`.
+        #[arg(long, default_value = "1", value_parser = compute::parse_dispatch)]
+        dispatch: [u32; 3],
+        /// Invocations to cover as `X[,Y[,Z]]`, e.g. the elements of the buffer, instead of
+        /// `--dispatch`. The workgroups are rounded up from the entry point's `@workgroup_size`.
+        #[arg(long, conflicts_with = "dispatch", value_parser = compute::parse_invocations)]
+        invocations: Option<[u32; 3]>,
+        /// Initial buffer contents, as binary or a `.hex` file, zeros if not given.
+        #[arg(long)]
+        input: Option<PathBuf>,
+        /// Size of the zeroed buffer in bytes, taken from the shader if not given.
+        #[arg(long, conflicts_with = "input")]
+        size: Option<u32>,
+        /// Write the buffer to a file instead of printing it as hex.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Compile a shader against a matrix of naga capabilities and validation flags.
+    #[cfg(feature = "matrix")]
+    Matrix {
+        /// Path or `http(s)://` URL of the WGSL shader.
+        shader: String,
+        /// Shader def as `NAME` or `NAME=VALUE` (bool, int or uint), can be repeated.
+        #[arg(short, long = "def", value_parser = shader_maker::parse_def)]
+        defs: Vec<(String, ShaderDefValue)>,
+    },
+    /// List every function of the composed shader with its signature.
+    Functions {
+        /// Path or `http(s)://` URL of the WGSL shader.
+        shader: String,
+        /// Shader def as `NAME` or `NAME=VALUE` (bool, int or uint), can be repeated.
+        #[arg(short, long = "def", value_parser = shader_maker::parse_def)]
+        defs: Vec<(String, ShaderDefValue)>,
+        /// Composable module to register before compiling, can be repeated.
+        #[arg(short, long = "module")]
+        modules: Vec<String>,
+    },
+    /// List the resources the composed shader binds, with the stages using them.
+    Bindings {
+        /// Path or `http(s)://` URL of the WGSL shader.
+        shader: String,
+        /// Shader def as `NAME` or `NAME=VALUE` (bool, int or uint), can be repeated.
+        #[arg(short, long = "def", value_parser = shader_maker::parse_def)]
+        defs: Vec<(String, ShaderDefValue)>,
+        /// Composable module to register before compiling, can be repeated.
+        #[arg(short, long = "module")]
+        modules: Vec<String>,
+    },
+    /// Write the import graph of a shader and its modules as Graphviz `.dot` source.
+    Graph {
+        /// Path or `http(s)://` URL of the WGSL shader.
+        shader: String,
+        /// Composable module to include in the graph, can be repeated.
+        #[arg(short, long = "module")]
+        modules: Vec<String>,
+        /// Write the graph to a file instead of stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Render with padded and exact-fit readback and check both give the same image and
+    /// that every pixel ends up at the right place.
+    ///
+    /// Without an adapter, e.g. on CI machines without a GPU, the checks are skipped.
+    Readback {
+        /// Path or `http(s)://` URL of the WGSL shader, `test.wgsl` if not given.
+        shader: Option<String>,
+        /// Render target width, exact-fit needs `width * 4` to be a multiple of 256.
+        #[arg(long, default_value_t = 64)]
+        width: u32,
+        /// Render target height.
+        #[arg(long, default_value_t = 64)]
+        height: u32,
+    },
+    /// Render one shader and sample the result in a second, without leaving the GPU.
+    Chain {
+        /// Path or `http(s)://` URL of a WGSL shader per pass, optionally followed by
+        /// `:ENTRY` for a fragment entry point other than `fs_main`. Every pass after the
+        /// first samples the previous one's output at `@group(0) @binding(1)` with a
+        /// sampler at `@binding(2)`.
+        #[arg(required = true)]
+        passes: Vec<String>,
+        /// Where to write the last pass's output.
+        #[arg(short, long, default_value = "chain.png")]
+        output: PathBuf,
+        /// Also save every pass's output as `pass_0.png`, `pass_1.png`, ...
+        #[arg(long)]
+        dump_intermediate: bool,
+    },
+    /// Save the per-pixel difference of two images, e.g. a render and its golden.
+    Diff {
+        /// First image.
+        a: PathBuf,
+        /// Second image, must be the same size as the first.
+        b: PathBuf,
+        /// Where to write the difference image.
+        #[arg(short, long, default_value = "diff.png")]
+        output: PathBuf,
+        /// Multiply differences by this factor so small ones become visible.
+        #[arg(long, default_value_t = 1)]
+        amplify: u8,
+    },
+    /// Render a shader with two sets of defs and save the difference of the two variants,
+    /// e.g. to check an `#ifdef` only changes what it should.
+    VariantDiff {
+        /// Path or `http(s)://` URL of the WGSL shader.
+        shader: String,
+        /// Def of the first variant as `NAME` or `NAME=VALUE`, can be repeated.
+        #[arg(short, long = "a-def", value_parser = shader_maker::parse_def)]
+        a_defs: Vec<(String, ShaderDefValue)>,
+        /// Def of the second variant as `NAME` or `NAME=VALUE`, can be repeated.
+        #[arg(short, long = "b-def", value_parser = shader_maker::parse_def)]
+        b_defs: Vec<(String, ShaderDefValue)>,
+        /// Composable module to register before compiling, can be repeated.
+        #[arg(short, long = "module")]
+        modules: Vec<String>,
+        /// Render width.
+        #[arg(long, default_value_t = 256)]
+        width: u32,
+        /// Render height.
+        #[arg(long, default_value_t = 256)]
+        height: u32,
+        /// Where to write the difference image.
+        #[arg(short, long, default_value = "diff.png")]
+        output: PathBuf,
+        /// Multiply differences by this factor so small ones become visible.
+        #[arg(long, default_value_t = 1)]
+        amplify: u8,
+    },
+    /// Print a minimal shader that works with the demo's render pipeline.
+    Scaffold {
+        /// Include a uniform block at `@group(0) @binding(0)`.
+        #[arg(long)]
+        uniform: bool,
+        /// Include a texture and sampler stub at `@group(0) @binding(1/2)`.
+        #[arg(long)]
+        texture: bool,
+        /// Write the shader to a file instead of stdout.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Render every shader in a directory to a thumbnail and build an index of them.
+    Gallery {
+        /// Directory containing `.wgsl` files.
+        dir: PathBuf,
+        /// Directory the thumbnails and index are written to.
+        #[arg(long, default_value = "gallery")]
+        output: PathBuf,
+    },
+    /// Render a shader with its uniform values read at runtime and baked in as defs, and compare.
+    Specialize {
+        /// Path or `http(s)://` URL of the WGSL shader.
+        shader: String,
+        /// Shader def as `NAME` or `NAME=VALUE` (bool, int or uint), can be repeated.
+        #[arg(short, long = "def", value_parser = shader_maker::parse_def)]
+        defs: Vec<(String, ShaderDefValue)>,
+        /// Uniform value as NAME=VALUE, in the order the shader's uniform struct declares them.
+        #[arg(long = "value", value_parser = specialize::parse_value)]
+        values: Vec<(String, u32)>,
+    },
+    /// Compose a shader and write it as SPIR-V, GLSL, expanded WGSL or a naga IR dump.
+    #[command(visible_alias = "compose")]
+    Transpile {
+        /// Path or `http(s)://` URL of the shader.
+        #[arg(required_unless_present = "input")]
+        shader: Option<String>,
+        /// The shader given as an option rather than positionally.
+        #[arg(
+            long = "shader",
+            visible_alias = "input",
+            value_name = "SHADER",
+            conflicts_with = "shader"
+        )]
+        input: Option<String>,
+        /// Shader def as `NAME` or `NAME=VALUE` (bool, int or uint), can be repeated.
+        #[arg(short, long = "def", value_parser = shader_maker::parse_def)]
+        defs: Vec<(String, ShaderDefValue)>,
+        /// Composable module to register before compiling, can be repeated.
+        #[arg(short, long = "module")]
+        modules: Vec<String>,
+        /// Language and stage of the shader, inferred from `.wgsl`, `.vert` and `.frag`.
+        #[arg(long, value_enum)]
+        shader_type: Option<ShaderKind>,
+        /// Output language.
+        #[arg(long, value_enum, default_value = "wgsl")]
+        to: transpile::Target,
+        /// Entry point to write, GLSL only.
+        #[arg(long, required_if_eq("to", "glsl"))]
+        entry_point: Option<String>,
+        /// SPIR-V version as `MAJOR.MINOR`.
+        #[arg(long, default_value = "1.0", value_parser = transpile::parse_spirv_version)]
+        spirv_version: (u8, u8),
+        /// SPIR-V capability the output may use, e.g. `Shader` or `Float64`, can be repeated.
+        /// Shaders needing one that wasn't given fail, without any all are allowed.
+        #[arg(long = "spirv-capability", value_parser = transpile::parse_spirv_capability)]
+        spirv_capabilities: Vec<naga::back::spv::Capability>,
+        /// GLSL version, e.g. `330` or `300es`.
+        #[arg(long, default_value = "330", value_parser = transpile::parse_glsl_version)]
+        glsl_version: naga::back::glsl::Version,
+        /// Write the output to a file instead of stdout.
+        #[arg(short, long, visible_alias = "out")]
+        output: Option<PathBuf>,
+    },
+    /// Render a shader to a PNG and render it again whenever it or a `--module` file changes.
+    #[cfg(feature = "watch")]
+    Watch {
+        /// Path of the WGSL shader to watch.
+        shader: PathBuf,
+        /// Shader def as `NAME` or `NAME=VALUE` (bool, int or uint), can be repeated.
+        #[arg(short, long = "def", value_parser = shader_maker::parse_def)]
+        defs: Vec<(String, ShaderDefValue)>,
+        /// Composable module to register before compiling, can be repeated.
+        #[arg(short, long = "module")]
+        modules: Vec<String>,
+        /// Where to write the rendered image.
+        #[arg(short, long, default_value = "image.png")]
+        output: PathBuf,
+    },
 }
 
-impl ShaderMaker {
-    pub fn new() -> Self {
-        let composer = Composer::default();
+/// Error `main` exits with, printed as the message itself rather than its `Debug` form.
+struct Failure(String);
 
-        Self { composer }
+impl std::fmt::Debug for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
     }
+}
 
-    /// Add a shader as a composable module so that it can be imported by other shaders.
-    pub fn add_composable(
-        &mut self,
-        source: &str,
-        module_name: &str,
-        shader_defs: &[&str],
-    ) {
-        let module_exists = self.composer.contains_module(module_name);
-
-        if !module_exists {
-            let mut shader_defs_map: HashMap<String, ShaderDefValue> = HashMap::new();
-            for def in shader_defs.iter() {
-                shader_defs_map.insert((*def).into(), Default::default());
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+type Result<T = (), E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+fn main() -> Result {
+    // Our own messages by default, wgpu's and naga's only for warnings, `RUST_LOG` overrides.
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("naga_oil_demo=info,warn"),
+    )
+    .init();
+
+    run(Cli::parse()).map_err(|e| Failure(e.to_string()).into())
+}
+
+fn run(cli: Cli) -> Result {
+    let gpu_options = cli.gpu_options();
+    match cli.command {
+        Some(Command::Adapters { shader, defs }) => {
+            let source = source::load(&shader)?;
+            let defs: HashMap<String, ShaderDefValue> = defs.into_iter().collect();
+            if !adapters::compare(&source, &defs) {
+                return Err("adapters don't agree on the shader".into());
+            }
+        }
+        Some(Command::Batch {
+            dir,
+            defs,
+            modules,
+            module_dirs,
+            jobs,
+        }) => {
+            let shader_defs: HashMap<String, ShaderDefValue> = defs.into_iter().collect();
+            let mut shader_maker = ShaderMaker::new();
+            for module_dir in &module_dirs {
+                let load = shader_maker.add_composable_dir(module_dir, shader_defs.clone());
+                if let Some((path, error)) = load.failed.first() {
+                    return Err(format!("failed to add {}:\n{error}", path.display()).into());
+                }
+            }
+            register_modules(&mut shader_maker, &modules, &shader_defs)?;
+
+            let jobs = jobs.unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+            });
+            let batch = batch::run(&shader_maker, &dir, &shader_defs, jobs)
+                .map_err(|e| format!("failed to list {}: {e}", dir.display()))?;
+            if !batch::print_report(&batch) {
+                return Err("some shaders failed to compile".into());
+            }
+        }
+        Some(Command::Bench {
+            shader,
+            defs,
+            iterations,
+        }) => {
+            let source = match shader {
+                Some(shader) => source::load(&shader)?,
+                None => include_str!("test.wgsl").into(),
+            };
+            let defs: HashMap<String, ShaderDefValue> = defs.into_iter().collect();
+            let gpu = render::Gpu::new(&gpu_options)?;
+            if !bench::run(&gpu, &source, &defs, iterations) {
+                return Err("benchmark failed".into());
+            }
+        }
+        Some(Command::Capabilities {
+            shader,
+            defs,
+            shader_type,
+        }) => {
+            let shader_type = ShaderKind::resolve(shader_type, &shader)?;
+            let source = source::load(&shader)?;
+            let defs: HashMap<String, ShaderDefValue> = defs.into_iter().collect();
+            if !requirements::run(&source, shader_type.into(), &defs) {
+                return Err("couldn't determine the required capabilities".into());
             }
+        }
+        Some(Command::Compute {
+            shader,
+            defs,
+            modules,
+            entry_point,
+            dispatch,
+            invocations,
+            input,
+            size,
+            output,
+        }) => {
+            let source = source::load(&shader)?;
+            let shader_defs: HashMap<String, ShaderDefValue> = defs.into_iter().collect();
+            let mut shader_maker = ShaderMaker::new();
+            register_modules(&mut shader_maker, &modules, &shader_defs)?;
 
-            match self
-                .composer
-                .add_composable_module(ComposableModuleDescriptor {
-                    source,
-                    shader_defs: shader_defs_map,
-                    as_name: Some(module_name.into()),
-                    ..Default::default()
-                }) {
-                Ok(module) => {
-                    println!(
-                        "Added composable module {} [{:?}]",
-                        module.name, module.shader_defs
-                    )
+            let module = shader_maker
+                .make_module_with_path(&source, &shader, shader_defs)
+                .map_err(|e| shader_maker.diagnostic(&e))?;
+
+            let storage = match (input, size) {
+                (Some(path), _) => buffer_file::load(&path)?,
+                (None, Some(size)) => vec![0; size as usize],
+                (None, None) => buffer_file::binding_size(&module, 0, 0)
+                    .map(|size| vec![0; size as usize])
+                    .ok_or("shader has no storage buffer at @group(0) @binding(0)")?,
+            };
+            buffer_file::check_size(&module, 0, 0, storage.len())?;
+
+            let dispatch = match invocations {
+                Some(invocations) => {
+                    let entry = compute::entry_point(&module, &entry_point)?;
+                    let dispatch = compute::dispatch_for(entry, invocations);
+                    log::info!(
+                        "Dispatching {dispatch:?} workgroups of {:?} for {invocations:?} invocations",
+                        entry.workgroup_size
+                    );
+                    dispatch
                 }
-                Err(e) => {
-                    println!("? -> {e:#?}")
+                None => dispatch,
+            };
+
+            let gpu = render::Gpu::new(&gpu_options)?;
+            let shader_source = wgpu::ShaderSource::Naga(std::borrow::Cow::Owned(module));
+            let data = compute::run_compute(&gpu, shader_source, &entry_point, dispatch, &storage)?;
+            match output {
+                Some(path) => write_file(&path, data)?,
+                None => compute::print_hex(&data),
+            }
+        }
+        #[cfg(feature = "matrix")]
+        Some(Command::Matrix { shader, defs }) => {
+            let source = source::load(&shader)?;
+            let defs: HashMap<String, ShaderDefValue> = defs.into_iter().collect();
+            if !matrix::run(&source, &defs) {
+                return Err("the shader failed part of the matrix".into());
+            }
+        }
+        Some(Command::Specialize {
+            shader,
+            defs,
+            values,
+        }) => {
+            let source = source::load(&shader)?;
+            let defs: HashMap<String, ShaderDefValue> = defs.into_iter().collect();
+            let gpu = render::Gpu::new(&gpu_options)?;
+            if !specialize::compare(&gpu, &source, defs, &values) {
+                return Err("the specialized render differs".into());
+            }
+        }
+        Some(Command::Functions {
+            shader,
+            defs,
+            modules,
+        }) => {
+            let source = source::load(&shader)?;
+            let mut shader_maker = ShaderMaker::new();
+            let shader_defs_map: HashMap<String, ShaderDefValue> = defs.into_iter().collect();
+
+            register_modules(&mut shader_maker, &modules, &shader_defs_map)?;
+
+            let module = shader_maker
+                .make_module_with_path(&source, &shader, shader_defs_map)
+                .map_err(|e| shader_maker.diagnostic(&e))?;
+            for signature in reflect::function_signatures(&module) {
+                println!("{signature}");
+            }
+        }
+        Some(Command::Bindings {
+            shader,
+            defs,
+            modules,
+        }) => {
+            let source = source::load(&shader)?;
+            let mut shader_maker = ShaderMaker::new();
+            let shader_defs_map: HashMap<String, ShaderDefValue> = defs.into_iter().collect();
+
+            register_modules(&mut shader_maker, &modules, &shader_defs_map)?;
+
+            let bindings = shader_maker
+                .reflect(&source, shader_defs_map)
+                .map_err(|e| shader_maker.diagnostic(&e))?;
+            for binding in bindings {
+                println!("{binding}");
+            }
+        }
+        Some(Command::Gallery { dir, output }) => {
+            let gpu = render::Gpu::new(&gpu_options)?;
+            let all_rendered = gallery::run(&gpu, &dir, &output)
+                .map_err(|e| format!("failed to build the gallery in {}: {e}", output.display()))?;
+            if !all_rendered {
+                return Err("some shaders failed to render".into());
+            }
+        }
+        Some(Command::Graph {
+            shader,
+            modules,
+            output,
+        }) => {
+            let source = source::load(&shader)?;
+            let modules = modules
+                .iter()
+                .map(|location| {
+                    let source = source::load(location)?;
+                    Ok((module_name(location, &source), source))
+                })
+                .collect::<Result<Vec<(String, String)>, String>>()?;
+
+            let dot = graph::dot(&shader, &source, &modules);
+            match output {
+                Some(path) => write_file(&path, dot)?,
+                None => print!("{dot}"),
+            }
+        }
+        Some(Command::Readback {
+            shader,
+            width,
+            height,
+        }) => {
+            let source = match shader {
+                Some(shader) => source::load(&shader)?,
+                None => include_str!("test.wgsl").into(),
+            };
+            let gpu = match render::Gpu::new(&gpu_options) {
+                Err(render::GpuError::NoAdapter) => {
+                    println!("No GPU adapter found, skipping the readback checks");
+                    return Ok(());
                 }
+                gpu => gpu?,
+            };
+            let coordinates_ok = check_pixel_coordinates(&gpu, (width, height));
+            let targets_ok = check_color_targets(&gpu, (width, height));
+            let fullscreen_ok = check_fullscreen_triangle(&gpu, (width, height));
+            if !check_readback(&gpu, &source, (width, height))
+                || !coordinates_ok
+                || !targets_ok
+                || !fullscreen_ok
+            {
+                return Err("readback check failed".into());
             }
-        };
+        }
+        Some(Command::Chain {
+            passes,
+            output,
+            dump_intermediate,
+        }) => render_chain(&gpu_options, &passes, &output, dump_intermediate)?,
+        Some(Command::Diff {
+            a,
+            b,
+            output,
+            amplify,
+        }) => {
+            if !diff::run(&a, &b, &output, amplify)? {
+                return Err("the images differ".into());
+            }
+        }
+        Some(Command::VariantDiff {
+            shader,
+            a_defs,
+            b_defs,
+            modules,
+            width,
+            height,
+            output,
+            amplify,
+        }) => {
+            let source = source::load(&shader)?;
+            let gpu = render::Gpu::new(&gpu_options)?;
+
+            let render_variant = |defs: Vec<(String, ShaderDefValue)>| -> Result<_> {
+                let shader_defs: HashMap<String, ShaderDefValue> = defs.into_iter().collect();
+                let mut shader_maker = ShaderMaker::new();
+                register_modules(&mut shader_maker, &modules, &shader_defs)?;
+                let shader_source = shader_maker
+                    .make_shader_with_path(&source, &shader, shader_defs)
+                    .map_err(|e| shader_maker.diagnostic(&e))?;
+                Ok(render::render_to_image(
+                    &gpu,
+                    shader_source,
+                    (width, height),
+                    render::RenderOptions::default().format,
+                )?)
+            };
+            let a = render_variant(a_defs)?;
+            let b = render_variant(b_defs)?;
+
+            if diff::compare(&a, &b, &output, amplify)? {
+                println!("The variants render identically");
+            } else {
+                println!("The variants differ");
+            }
+        }
+        Some(Command::Scaffold {
+            uniform,
+            texture,
+            output,
+        }) => {
+            let source = scaffold::generate(uniform, texture);
+            match output {
+                Some(path) => write_file(&path, source)?,
+                None => print!("{source}"),
+            }
+        }
+        Some(Command::Transpile {
+            shader,
+            input,
+            defs,
+            modules,
+            shader_type,
+            to,
+            entry_point,
+            spirv_version,
+            spirv_capabilities,
+            glsl_version,
+            output,
+        }) => {
+            let shader = shader.or(input).expect("clap requires the shader");
+            let shader_type = ShaderKind::resolve(shader_type, &shader)?;
+            let source = source::load(&shader)?;
+            let shader_defs: HashMap<String, ShaderDefValue> = defs.into_iter().collect();
+            let mut shader_maker = ShaderMaker::new();
+            register_modules(&mut shader_maker, &modules, &shader_defs)?;
+            shader_maker.set_shader_type(shader_type.into());
+
+            if to == transpile::Target::Spirv && output.is_none() {
+                return Err("SPIR-V is binary, write it to a file with --output".into());
+            }
+
+            let result = match to {
+                transpile::Target::Spirv => {
+                    let options = naga::back::spv::Options {
+                        lang_version: spirv_version,
+                        capabilities: (!spirv_capabilities.is_empty())
+                            .then(|| spirv_capabilities.into_iter().collect()),
+                        ..Default::default()
+                    };
+                    shader_maker
+                        .make_spirv(&source, shader_defs, &options)
+                        .map(|words| bytemuck::cast_slice(&words).to_vec())
+                }
+                transpile::Target::Glsl => {
+                    let options = naga::back::glsl::Options {
+                        version: glsl_version,
+                        ..Default::default()
+                    };
+                    let entry_point = entry_point.expect("clap requires it for GLSL");
+                    shader_maker
+                        .make_glsl(&source, shader_defs, &entry_point, &options)
+                        .map(String::into_bytes)
+                }
+                transpile::Target::Wgsl => shader_maker
+                    .make_wgsl_string(&source, shader_defs)
+                    .map(String::into_bytes),
+                transpile::Target::Preprocessed => shader_maker
+                    .preprocess_to_wgsl(&source, shader_defs)
+                    .map(String::into_bytes),
+                transpile::Target::Ir => shader_maker
+                    .make_shader_debug(&source, shader_defs, shader_maker::DebugFormat::Ir)
+                    .map(String::into_bytes),
+            };
+
+            let bytes = result.map_err(|e| shader_maker.diagnostic(&e))?;
+            match output {
+                Some(path) => write_file(&path, bytes)?,
+                None => print!("{}", String::from_utf8_lossy(&bytes)),
+            }
+        }
+        #[cfg(feature = "watch")]
+        Some(Command::Watch {
+            shader,
+            defs,
+            modules,
+            output,
+        }) => {
+            let shader_defs: HashMap<String, ShaderDefValue> = defs.into_iter().collect();
+            let make_shader_maker = || {
+                let mut shader_maker = ShaderMaker::new();
+                register_modules(&mut shader_maker, &modules, &shader_defs)?;
+                Ok(shader_maker)
+            };
+            // Modules fetched from URLs are registered, but can't be watched.
+            let module_paths: Vec<PathBuf> = modules
+                .iter()
+                .filter(|location| !source::is_url(location))
+                .map(PathBuf::from)
+                .collect();
+
+            let gpu = render::Gpu::new(&gpu_options)?;
+            watch::watch_shader(
+                &gpu,
+                make_shader_maker,
+                &shader,
+                &module_paths,
+                &shader_defs,
+                &output,
+            )
+            .map_err(|e| format!("failed to watch {}: {e}", shader.display()))?;
+        }
+        None if cli.info => adapters::print_info(&render::Gpu::new(&gpu_options)?),
+        None if cli.frames.is_some() => animation_demo(&cli)?,
+        #[cfg(feature = "preview")]
+        None if cli.preview => preview_demo(&cli)?,
+        None => render_demo(&cli)?,
+    }
+
+    Ok(())
+}
+
+/// `std::fs::write`, with the path in the error.
+fn write_file(path: &std::path::Path, contents: impl AsRef<[u8]>) -> Result<(), String> {
+    std::fs::write(path, contents).map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+/// Split a chain pass like `blur.wgsl:fs_blur_h` into the shader and its fragment entry point.
+///
+/// A suffix that isn't an identifier, like the rest of a URL, is part of the location.
+fn split_entry_point(pass: &str) -> (&str, &str) {
+    match pass.rsplit_once(':') {
+        Some((location, entry_point))
+            if !entry_point.is_empty()
+                && entry_point
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+        {
+            (location, entry_point)
+        }
+        _ => (pass, "fs_main"),
+    }
+}
+
+/// Compose the shaders, render them as a chain and save the last one's output.
+///
+/// With `dump_intermediate`, each pass's output is also saved as `pass_<index>.png`.
+fn render_chain(
+    gpu_options: &render::GpuOptions,
+    passes: &[String],
+    output: &std::path::Path,
+    dump_intermediate: bool,
+) -> Result<(), String> {
+    let mut descs = Vec::new();
+    for (index, pass) in passes.iter().enumerate() {
+        let (location, entry_point) = split_entry_point(pass);
+        let mut shader_maker = ShaderMaker::new();
+        let shader_source = shader_maker
+            .make_shader_with_path(&source::load(location)?, location, HashMap::new())
+            .map_err(|e| shader_maker.diagnostic(&e))?;
+        descs.push(chain::PassDesc {
+            shader_source,
+            entry_point,
+            reads_previous: index > 0,
+        });
+    }
+
+    let gpu = render::Gpu::new(gpu_options).map_err(|e| e.to_string())?;
+    let options = render::RenderOptions::default();
+    let outputs =
+        chain::render_chain(&gpu, descs, &options, dump_intermediate).map_err(|e| e.to_string())?;
+
+    let save = |path: &std::path::Path, name: &str, data: &[u8]| {
+        let metadata = output::ImageMetadata::new(name, &[]);
+        output::save_png_with_metadata(path, options.size.0, options.size.1, data, &metadata)
+            .map_err(|e| format!("failed to save {}: {e}", path.display()))
+    };
+
+    if dump_intermediate {
+        for (index, (data, name)) in outputs.iter().zip(passes).enumerate() {
+            let path = PathBuf::from(format!("pass_{index}.png"));
+            save(&path, name, data)?;
+            println!("Pass {index} written to {}", path.display());
+        }
+    }
+
+    let data = outputs.last().expect("the chain has at least one pass");
+    save(output, &passes.join(" -> "), data)?;
+
+    println!("Chained render written to {}", output.display());
+    Ok(())
+}
+
+/// Render a shader that writes each pixel's coordinates and check every pixel read back
+/// holds its own, which fails if row padding was left in or stripped at the wrong offsets.
+fn check_pixel_coordinates(gpu: &render::Gpu, size: (u32, u32)) -> bool {
+    let shader_source = ShaderMaker::new()
+        .make_shader_with_values(include_str!("coordinates.wgsl"), HashMap::new())
+        .expect("the coordinate shader compiles");
+
+    let pixels = match render::render_to_rgba(gpu, shader_source, size) {
+        Ok(pixels) => pixels,
+        Err(e) => {
+            println!("{e}");
+            return false;
+        }
+    };
+
+    for (index, pixel) in pixels.chunks_exact(4).enumerate() {
+        let (x, y) = (index as u32 % size.0, index as u32 / size.0);
+        let expected = [(x % 256) as u8, (y % 256) as u8, 0, 255];
+        if pixel != expected {
+            println!("Pixel ({x}, {y}) is {pixel:?}, expected {expected:?}");
+            return false;
+        }
+    }
+
+    println!(
+        "Every pixel of a {}x{} render is where it belongs",
+        size.0, size.1
+    );
+    true
+}
+
+/// Draw red into one color target and green into another, check the shader is refused
+/// with a single target, and that each target reads back as its color.
+fn check_color_targets(gpu: &render::Gpu, size: (u32, u32)) -> bool {
+    const EXPECTED: [[u8; 4]; 2] = [[255, 0, 0, 255], [0, 255, 0, 255]];
+
+    let mut shader_maker = ShaderMaker::new();
+    let mut shader_source = || {
+        shader_maker
+            .make_shader_with_values(include_str!("color_targets.wgsl"), HashMap::new())
+            .expect("the color targets shader compiles")
+    };
+
+    let options = render::RenderOptions {
+        size,
+        ..Default::default()
+    };
+    match render::render(gpu, shader_source(), &options) {
+        Err(render::RenderError::ColorTargetMismatch { .. }) => {}
+        result => {
+            println!(
+                "Rendering two outputs into one color target gave {:?} instead of an error",
+                result.map(|pixels| pixels.len())
+            );
+            return false;
+        }
+    }
+
+    let options = render::RenderOptions {
+        color_targets: 2,
+        ..options
+    };
+    let pixels = match render::render(gpu, shader_source(), &options) {
+        Ok(pixels) => pixels,
+        Err(e) => {
+            println!("{e}");
+            return false;
+        }
+    };
+
+    let (x, y) = (size.0 / 2, size.1 / 2);
+    let target_size = 4 * (size.0 * size.1) as usize;
+    for (target, expected) in EXPECTED.iter().enumerate() {
+        let offset = target * target_size + 4 * (y * size.0 + x) as usize;
+        let pixel = &pixels[offset..offset + 4];
+        if pixel != expected {
+            println!(
+                "Center pixel ({x}, {y}) of target {target} is {pixel:?}, expected {expected:?}"
+            );
+            return false;
+        }
+    }
+
+    println!("Both color targets read back as the colors the shader wrote to them");
+    true
+}
+
+/// Draw the buffer-less triangle of `fullscreen.wgsl` and check it covers every pixel
+/// with the uv of the pixel's center, give or take rounding.
+fn check_fullscreen_triangle(gpu: &render::Gpu, size: (u32, u32)) -> bool {
+    const TOLERANCE: u8 = 1;
+
+    let shader_source = ShaderMaker::new()
+        .make_shader_with_values(include_str!("fullscreen.wgsl"), HashMap::new())
+        .expect("the fullscreen shader compiles");
+
+    let options = render::RenderOptions {
+        size,
+        ..Default::default()
+    };
+    let pixels = match render::render_fullscreen(gpu, shader_source, &options) {
+        Ok(pixels) => pixels,
+        Err(e) => {
+            println!("{e}");
+            return false;
+        }
+    };
+
+    let uv = |coordinate: u32, size: u32| ((coordinate as f32 + 0.5) / size as f32 * 255.0).round();
+    for (index, pixel) in pixels.chunks_exact(4).enumerate() {
+        let (x, y) = (index as u32 % size.0, index as u32 / size.0);
+        let expected = [uv(x, size.0) as u8, uv(y, size.1) as u8, 0, 255];
+        if pixel
+            .iter()
+            .zip(expected)
+            .any(|(actual, expected)| actual.abs_diff(expected) > TOLERANCE)
+        {
+            println!("Fullscreen triangle pixel ({x}, {y}) is {pixel:?}, expected {expected:?}");
+            return false;
+        }
     }
 
-    /// Make a naga module using the shader.
-    pub fn make_shader(
-        &mut self,
-        source: &str,
-        shader_defs: &[&str],
-    ) -> Option<wgpu::ShaderSource> {
-        let mut shader_defs_map: HashMap<String, ShaderDefValue> = HashMap::new();
-        for def in shader_defs.iter() {
-            shader_defs_map.insert((*def).into(), Default::default());
+    println!("The fullscreen triangle covers every pixel with its uv");
+    true
+}
+
+/// Render the shader once per readback strategy and compare the resulting pixels.
+fn check_readback(gpu: &render::Gpu, source: &str, size: (u32, u32)) -> bool {
+    let unpadded = 4 * size.0;
+    for alignment in [
+        render::ReadbackAlignment::Auto,
+        render::ReadbackAlignment::Padded,
+        render::ReadbackAlignment::Exact,
+    ] {
+        match alignment.bytes_per_row(unpadded) {
+            Ok((bytes_per_row, padded)) => println!(
+                "{alignment:?}: {bytes_per_row} bytes per row{}",
+                if padded { ", padding stripped" } else { "" }
+            ),
+            Err(e) => println!("{alignment:?}: {e}"),
         }
+    }
 
-        match self.composer.make_naga_module(NagaModuleDescriptor {
-            source,
-            shader_defs: shader_defs_map.into(),
+    let mut images = Vec::new();
+    for alignment in [
+        render::ReadbackAlignment::Padded,
+        render::ReadbackAlignment::Exact,
+    ] {
+        let Ok(shader_source) = ShaderMaker::new().make_shader_with_values(source, HashMap::new())
+        else {
+            println!("Shader failed to compile");
+            return false;
+        };
+
+        let options = render::RenderOptions {
+            size,
+            readback: alignment,
             ..Default::default()
-        }) {
-            Ok(module) => Some(wgpu::ShaderSource::Naga(Cow::Owned(module))),
+        };
+        match render::render(gpu, shader_source, &options) {
+            Ok(pixels) => images.push(pixels),
             Err(e) => {
-                println!("{}", e.emit_to_string(&self.composer));
-                None
+                println!("{e}");
+                return false;
             }
         }
     }
+
+    if images[0] == images[1] {
+        println!("Padded and exact-fit readback produce identical images");
+        true
+    } else {
+        println!("Padded and exact-fit readback produce different images");
+        false
+    }
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub(crate) struct Vertex2d {
-    pub(crate) position: [f32; 2],
+/// Add every module location as a composable module with the given defs, stopping at the
+/// first one that fails.
+fn register_modules(
+    shader_maker: &mut ShaderMaker,
+    locations: &[String],
+    shader_defs: &HashMap<String, ShaderDefValue>,
+) -> Result<(), String> {
+    for location in locations {
+        let source = source::load(location)?;
+        let name = module_name(location, &source);
+        shader_maker
+            .add_composable_with_path(&source, location, &name, shader_defs.clone())
+            .map_err(|e| {
+                format!(
+                    "failed to add composable module {name}:\n{}",
+                    shader_maker.diagnostic(&e)
+                )
+            })?;
+        log::info!("Added composable module {name}");
+    }
+
+    Ok(())
 }
 
-pub trait VertexBuffer {
-    /// Vertex buffer layout provided to a pipeline.
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
+/// Modules are named by their `#define_import_path`, or the file name without one.
+fn module_name(location: &str, source: &str) -> String {
+    let (name, _, _) = naga_oil::compose::get_preprocessor_data(source);
+    name.unwrap_or_else(|| {
+        std::path::Path::new(location)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    })
+}
+
+fn print_modules(shader_maker: &ShaderMaker) {
+    let modules = shader_maker.list_modules();
+    println!("{} registered module(s)", modules.len());
+    for name in modules {
+        let mut defs: Vec<String> = shader_maker
+            .module_defs(&name)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(def, value)| format!("{def}={}", sweep::value_string(&value)))
+            .collect();
+        defs.sort();
+        println!("    {name} {}", defs.join(" "));
+    }
 }
 
-impl VertexBuffer for Vertex2d {
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex2d>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[wgpu::VertexAttribute {
-                // Position.
-                offset: 0,
-                shader_location: 0,
-                format: wgpu::VertexFormat::Float32x2,
-            }],
+/// The maker for the shader given on the top-level command line, with its modules
+/// registered, and the defs to make it with.
+fn demo_maker(
+    cli: &Cli,
+    push_constants: bool,
+) -> Result<(ShaderMaker, Vec<(String, ShaderDefValue)>), String> {
+    if cli.skip_validation {
+        log::warn!("Shader validation is disabled, the output is UNVALIDATED");
+    }
+    let mut builder = ShaderMaker::builder().skip_validation(cli.skip_validation);
+    if push_constants {
+        builder = builder.capabilities(naga::valid::Capabilities::PUSH_CONSTANT);
+    }
+    if let Some(path) = &cli.defs_file {
+        builder = builder.shader_defs_file(path).map_err(|e| e.to_string())?;
+    }
+    let mut shader_maker = builder.build();
+
+    // The file defs are listed too, for variant names and metadata.
+    let mut shader_defs: Vec<(String, ShaderDefValue)> = vec![("BLUE".into(), Default::default())];
+    shader_defs.extend(shader_maker.file_defs().clone());
+    shader_defs.extend(cli.defs.iter().cloned());
+    shader_defs.extend(cli.int_defs.iter().cloned());
+
+    let module_defs: HashMap<String, ShaderDefValue> = shader_defs.iter().cloned().collect();
+    for dir in &cli.module_dirs {
+        let load = shader_maker.add_composable_dir(dir, module_defs.clone());
+        for name in &load.added {
+            log::info!("Added composable module {name}");
+        }
+        for (path, error) in &load.failed {
+            log::error!("Failed to add {}: {error}", path.display());
         }
     }
+    register_modules(&mut shader_maker, &cli.modules, &module_defs)?;
+    for location in &cli.prepends {
+        shader_maker.prepend_source(&source::load(location)?);
+    }
+
+    if cli.list_modules {
+        print_modules(&shader_maker);
+    }
+
+    Ok((shader_maker, shader_defs))
 }
 
-fn main() {
-    // Context for all other wgpu objects.
-    let instance = wgpu::Instance::default();
-
-    // Handle to a physical graphics and/or compute device.
-    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::default(),
-        compatible_surface: None,
-        force_fallback_adapter: false,
-    }))
-        .unwrap();
-
-    // Use the adapter to create a device and a queue.
-    let (device, queue) = pollster::block_on(adapter.request_device(
-        &wgpu::DeviceDescriptor {
-            features: wgpu::Features::empty(),
-            limits: wgpu::Limits::default(),
-            label: None,
-        },
-        None,
-    ))
-        .unwrap();
+/// Draw `--shader`, or `test.wgsl`, in a window at `--size` until it is closed.
+#[cfg(feature = "preview")]
+fn preview_demo(cli: &Cli) -> Result {
+    let source = match &cli.shader {
+        Some(location) => source::load(location)?,
+        None => include_str!("test.wgsl").into(),
+    };
 
-    let mut shader_maker = ShaderMaker::new();
+    let (mut shader_maker, shader_defs) = demo_maker(cli, false)?;
+    let shader_source = shader_maker
+        .make_shader_with_path(
+            &source,
+            cli.shader.as_deref().unwrap_or("test.wgsl"),
+            shader_defs.into_iter().collect(),
+        )
+        .map_err(|e| shader_maker.diagnostic(&e))?;
 
-    let shader_source = shader_maker.make_shader(include_str!("test.wgsl"), &["BLUE"]);
+    preview::run(
+        shader_source,
+        cli.size,
+        cli.frame_uniforms,
+        &cli.gpu_options(),
+    )
+}
 
-    let shader_desc = wgpu::ShaderModuleDescriptor {
-        label: None,
-        source: shader_source.unwrap(),
+/// Render `--frames` frames of `--shader`, or `test.wgsl`, to `--output` with the frame
+/// number appended to the file stem, or into a `.gif` output as an animation.
+fn animation_demo(cli: &Cli) -> Result {
+    let frames = cli.frames.unwrap_or(1);
+    let (shader_path, source) = match &cli.shader {
+        Some(location) => (location.as_str(), source::load(location)?),
+        None => ("src/test.wgsl", include_str!("test.wgsl").into()),
     };
+    let gpu = render::Gpu::new(&cli.gpu_options())?;
 
-    let shader = device.create_shader_module(shader_desc);
-
-    let texture_size = (128u32, 128u32);
-    let format = wgpu::TextureFormat::Rgba8Unorm;
-    let u32_size = std::mem::size_of::<u32>() as u32;
-
-    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: None,
-        bind_group_layouts: &[],
-        push_constant_ranges: &[],
-    });
-
-    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: None,
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: "vs_main",
-            buffers: &[Vertex2d::desc()],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            targets: &[Some(wgpu::ColorTargetState {
-                format,
-                blend: None,
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
-        }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: None,
-            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-            polygon_mode: wgpu::PolygonMode::Fill,
-            // Requires Features::DEPTH_CLIP_CONTROL
-            unclipped_depth: false,
-            // Requires Features::CONSERVATIVE_RASTERIZATION
-            conservative: false,
-        },
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
-        },
-        // If the pipeline will be used with a multiview resource pass, this
-        // indicates how many array layers the attachments will have.
-        multiview: None,
-    });
-
-    let vertices = [
-        Vertex2d {
-            position: [-1.0, 1.0],
-        },
-        Vertex2d {
-            position: [-1.0, -1.0],
-        },
-        Vertex2d {
-            position: [1.0, -1.0],
-        },
-        Vertex2d {
-            position: [1.0, 1.0],
-        },
-    ];
-
-    let indices = [0, 1, 2, 2, 3, 0];
-
-    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("vertex buffer")),
-        contents: bytemuck::cast_slice(&vertices),
-        usage: wgpu::BufferUsages::VERTEX,
-    });
-
-    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("index buffer")),
-        contents: bytemuck::cast_slice(&indices),
-        usage: wgpu::BufferUsages::INDEX,
-    });
-
-    let texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: None,
-        size: Extent3d {
-            width: texture_size.0,
-            height: texture_size.1,
-            depth_or_array_layers: 1,
-        },
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format,
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
-        view_formats: &[],
-    });
-
-    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some(&format!("staging buffer")),
-        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-        size: (align_up(
-            u32_size * texture_size.0,
-            wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
-        ) * texture_size.1) as BufferAddress,
-        mapped_at_creation: false,
-    });
-
-    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-        label: Some("main render encoder"),
-    });
+    let (mut shader_maker, shader_defs) = demo_maker(cli, false)?;
+    let defs: Vec<String> = shader_defs
+        .iter()
+        .map(|(name, value)| format!("{name}={}", sweep::value_string(value)))
+        .collect();
+    let shader_source = shader_maker
+        .make_shader_with_path(&source, shader_path, shader_defs.into_iter().collect())
+        .map_err(|e| shader_maker.diagnostic(&e))?;
 
-    {
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("main render pass"),
-            color_attachments: &[
-                // This is what @location(0) in the fragment shader targets.
-                Some(wgpu::RenderPassColorAttachment {
-                    view: &texture_view, // Change this to change where to draw.
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: StoreOp::Store,
-                    },
-                }),
-            ],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
+    let options = render::RenderOptions {
+        size: cli.size,
+        format: cli.format.into(),
+        clear_color: (!cli.no_clear).then(|| cli.clear.unwrap_or(render::CLEAR_COLOR)),
+        blend: cli.blend.state(),
+        blend_constant: cli.blend_constant,
+        timeout: cli.timeout,
+        vertex_entry: &cli.vertex_entry,
+        fragment_entry: &cli.fragment_entry,
+        ..Default::default()
+    };
 
-        render_pass.set_pipeline(&pipeline);
+    let defs: Vec<&str> = defs.iter().map(String::as_str).collect();
+    let metadata = if cli.deterministic {
+        output::ImageMetadata::deterministic(shader_path, &defs)
+    } else {
+        output::ImageMetadata::new(shader_path, &defs)
+    };
+    let start = std::time::Instant::now();
 
-        // Set vertex buffer for VertexInput.
-        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+    if cli
+        .output
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gif"))
+    {
+        let error = |e: std::io::Error| format!("failed to write {}: {e}", cli.output.display());
+        let file = std::fs::File::create(&cli.output).map_err(error)?;
+        let delay = (100.0 / cli.fps).round().clamp(1.0, u16::MAX as f32) as u16;
+        let mut writer = gif::GifWriter::new(std::io::BufWriter::new(file), options.size, delay)
+            .map_err(error)?;
 
-        render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        animation::render_frames(
+            &gpu,
+            shader_source,
+            &options,
+            frames,
+            cli.fps,
+            cli.time,
+            |_, data| -> Result {
+                let rgba = if cli.encode_srgb {
+                    render::to_srgb8(options.format, data)?
+                } else {
+                    render::to_rgba8(options.format, data)?
+                };
+                writer.write_frame(&rgba).map_err(error)?;
+                Ok(())
+            },
+        )?;
+        writer.finish().map_err(error)?;
 
-        render_pass.draw_indexed(0..6, 0, 0..1);
+        println!(
+            "Rendered {frames} frames into {} in {:?}",
+            cli.output.display(),
+            start.elapsed()
+        );
+        return Ok(());
     }
 
-    encoder.copy_texture_to_buffer(
-        wgpu::ImageCopyTexture {
-            aspect: wgpu::TextureAspect::All,
-            texture: &texture,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
-        },
-        wgpu::ImageCopyBuffer {
-            buffer: &staging_buffer,
-            layout: wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(align_up(
-                    u32_size * texture_size.0,
-                    wgpu::COPY_BYTES_PER_ROW_ALIGNMENT,
-                )),
-                rows_per_image: Some(texture_size.1),
-            },
-        },
-        wgpu::Extent3d {
-            width: texture_size.0 as u32,
-            height: texture_size.1 as u32,
-            depth_or_array_layers: 1,
+    animation::render_frames(
+        &gpu,
+        shader_source,
+        &options,
+        frames,
+        cli.fps,
+        cli.time,
+        |frame, data| -> Result {
+            let path = output::with_suffix(&cli.output, &format!("_{frame:04}"));
+            output::save_image(
+                &path,
+                options.size.0,
+                options.size.1,
+                options.format,
+                data,
+                cli.encode_srgb,
+                &metadata,
+            )
+            .map_err(|e| format!("failed to save {}: {e}", path.display()))?;
+            Ok(())
         },
-    );
+    )?;
 
-    queue.submit(Some(encoder.finish()));
+    println!("Rendered {frames} frames in {:?}", start.elapsed());
+    Ok(())
+}
 
-    let buffer_slice = staging_buffer.slice(..);
+/// Render `--shader`, or `test.wgsl`, to `--output`, or to one `<stem>_<DEF>_<VALUE>.png` per swept variant.
+fn render_demo(cli: &Cli) -> Result {
+    let skip_validation = cli.skip_validation;
+    if cli.cubemap && cli.size.0 != cli.size.1 {
+        return Err(format!(
+            "cubemap faces are square, got --size {}x{}",
+            cli.size.0, cli.size.1
+        )
+        .into());
+    }
+    let mut mesh = cli.mesh.as_deref().map(obj::load).transpose()?;
+    if let Some(mesh) = &mut mesh {
+        mesh.indices = mesh
+            .indices
+            .to_format(cli.index_format.into())
+            .ok_or_else(|| {
+                format!(
+                    "the mesh has {} vertices, too many for 16-bit indices",
+                    mesh.vertices.len()
+                )
+            })?;
+    }
+    let gpu = render::Gpu::new(&cli.gpu_options())?;
 
-    let (tx, rx) = futures_intrusive::channel::shared::oneshot_channel();
-    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
-        tx.send(result).unwrap();
-    });
+    let stencil_mask = cli
+        .stencil_mask
+        .as_ref()
+        .map(|path| {
+            stencil::StencilMask::load(path)
+                .map_err(|e| format!("failed to load stencil mask {}: {e}", path.display()))
+        })
+        .transpose()?;
 
-    device.poll(wgpu::Maintain::Wait);
-    pollster::block_on(rx.receive()).unwrap().unwrap();
+    let (shader_path, source) = match &cli.shader {
+        Some(location) => (location.as_str(), source::load(location)?),
+        None => ("src/test.wgsl", include_str!("test.wgsl").into()),
+    };
+    let shader_kind = ShaderKind::resolve(cli.shader_type, shader_path)?;
 
-    let data = buffer_slice.get_mapped_range();
+    let vertex_shader = cli
+        .vertex_shader
+        .as_deref()
+        .map(|location| -> Result<_, String> {
+            let kind = ShaderKind::resolve(None, location)?;
+            if kind == ShaderKind::GlslFragment {
+                return Err(format!(
+                    "{location} is a fragment shader, --vertex-shader needs a vertex shader"
+                ));
+            }
+            Ok((location, source::load(location)?, kind))
+        })
+        .transpose()?;
+
+    match (shader_kind, &vertex_shader) {
+        (ShaderKind::GlslVertex, _) => {
+            return Err("a GLSL vertex shader can't be rendered on its own, pass it as --vertex-shader along with a fragment shader".into());
+        }
+        (ShaderKind::GlslFragment, None) => {
+            return Err(
+                "GLSL fragment shaders have no vertex stage, pass one with --vertex-shader".into(),
+            );
+        }
+        _ => {}
+    }
+    // naga names the entry point of a GLSL shader `main`.
+    let entry_point = |kind: ShaderKind, entry_point| match kind {
+        ShaderKind::Wgsl => entry_point,
+        ShaderKind::GlslVertex | ShaderKind::GlslFragment => "main",
+    };
+    let vertex_kind = vertex_shader
+        .as_ref()
+        .map_or(shader_kind, |(_, _, kind)| *kind);
 
-    use image::{ImageBuffer, Rgba};
-    let buffer =
-        ImageBuffer::<Rgba<u8>, _>::from_raw(texture_size.0, texture_size.1, data).unwrap();
-    buffer.save("image.png").unwrap();
+    let uniforms = if cli.frame_uniforms {
+        let frame = render::FrameUniforms::new(cli.time, cli.size);
+        Some(bytemuck::bytes_of(&frame).to_vec())
+    } else {
+        cli.uniform_file
+            .as_deref()
+            .map(buffer_file::load)
+            .transpose()?
+    };
 
-    // Have to drop the BufferView before unmapping.
-    std::mem::drop(buffer);
+    let push_constants = cli
+        .push_constant_file
+        .as_deref()
+        .map(buffer_file::load)
+        .transpose()?;
 
-    staging_buffer.unmap();
-}
+    let input_texture = (!cli.input_textures.is_empty())
+        .then(|| {
+            texture::InputTexture::load(
+                cli.texture_dimension,
+                cli.input_color_space,
+                texture::Sampler {
+                    filter: cli.sampler_filter,
+                    address_mode: cli.sampler_address_mode,
+                },
+                &cli.input_textures,
+            )
+        })
+        .transpose()?;
 
-pub const fn align_up(num: u32, align: u32) -> u32 {
-    ((num) + ((align) - 1)) & !((align) - 1)
+    let (mut shader_maker, shader_defs) = demo_maker(cli, push_constants.is_some())?;
+
+    let options = render::RenderOptions {
+        stencil_mask: stencil_mask.as_ref(),
+        uniforms: uniforms.as_deref(),
+        push_constants: push_constants.as_deref(),
+        input_texture: input_texture.as_ref(),
+        size: cli.size,
+        format: cli.format.into(),
+        sample_count: cli.samples,
+        clear_color: (!cli.no_clear).then(|| cli.clear.unwrap_or(render::CLEAR_COLOR)),
+        blend: cli.blend.state(),
+        blend_constant: cli.blend_constant,
+        depth_test: cli.depth.then_some(wgpu::CompareFunction::Less),
+        depth_only: cli.depth_only,
+        timeout: cli.timeout,
+        layers: if cli.cubemap { 6 } else { cli.layers },
+        color_targets: cli.color_targets,
+        vertex_entry: entry_point(vertex_kind, &cli.vertex_entry),
+        fragment_entry: entry_point(shader_kind, &cli.fragment_entry),
+        profile: cli.profile,
+        ..Default::default()
+    };
+
+    let mut failed = 0;
+
+    for variant in sweep::variants(&cli.def_sweeps) {
+        let mut shader_defs_map: HashMap<String, ShaderDefValue> =
+            shader_defs.iter().cloned().collect();
+
+        let mut file_name = cli
+            .output
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "image".into());
+        let mut variant_defs: Vec<String> = shader_defs
+            .iter()
+            .map(|(name, value)| match value {
+                ShaderDefValue::Bool(true) => name.clone(),
+                value => format!("{name}={}", sweep::value_string(value)),
+            })
+            .collect();
+        for (name, value) in &variant {
+            let value_string = sweep::value_string(value);
+            file_name.push_str(&format!("_{name}_{value_string}"));
+            variant_defs.push(format!("{name}={value_string}"));
+            shader_defs_map.insert(name.clone(), *value);
+        }
+        if let Some(extension) = cli.output.extension() {
+            file_name.push_str(&format!(".{}", extension.to_string_lossy()));
+        }
+        let output_path = cli.output.with_file_name(file_name);
+
+        let vertex_module = match &vertex_shader {
+            Some((location, vertex_source, kind)) => {
+                shader_maker.set_shader_type((*kind).into());
+                match shader_maker.make_module_with_path(
+                    vertex_source,
+                    location,
+                    shader_defs_map.clone(),
+                ) {
+                    Ok(module) => Some(module),
+                    Err(e) => {
+                        log::error!("{}", shader_maker.diagnostic(&e));
+                        println!("[fail] {}", variant_defs.join(" "));
+                        failed += 1;
+                        continue;
+                    }
+                }
+            }
+            None => None,
+        };
+        let options = render::RenderOptions {
+            vertex_shader: vertex_module.as_ref(),
+            ..options
+        };
+
+        let start = std::time::Instant::now();
+        shader_maker.set_shader_type(shader_kind.into());
+        let shader_source =
+            shader_maker.make_shader_with_path(&source, shader_path, shader_defs_map);
+        if skip_validation {
+            log::warn!("Composed shader in {:?} (UNVALIDATED)", start.elapsed());
+        } else {
+            log::info!("Composed shader in {:?} (validated)", start.elapsed());
+        }
+
+        let shader_source = match shader_source {
+            Ok(shader_source) => shader_source,
+            Err(e) => {
+                log::error!("{}", shader_maker.diagnostic(&e));
+                println!("[fail] {}", variant_defs.join(" "));
+                failed += 1;
+                continue;
+            }
+        };
+
+        let module = match &shader_source {
+            wgpu::ShaderSource::Naga(module) => Some(module.as_ref()),
+            _ => None,
+        };
+        let named_uniforms = match (!cli.uniforms.is_empty())
+            .then(|| uniforms::pack(module, &cli.uniforms))
+            .transpose()
+        {
+            Ok(named_uniforms) => named_uniforms,
+            Err(e) => {
+                log::error!("{e}");
+                println!("[fail] {}", variant_defs.join(" "));
+                failed += 1;
+                continue;
+            }
+        };
+        let options = render::RenderOptions {
+            uniforms: named_uniforms.as_deref().or(options.uniforms),
+            ..options
+        };
+
+        if let (Some(uniforms), wgpu::ShaderSource::Naga(module)) = (&uniforms, &shader_source) {
+            if let Err(e) = buffer_file::check_size(module, 0, 0, uniforms.len()) {
+                log::error!("{e}");
+                println!("[fail] {}", variant_defs.join(" "));
+                failed += 1;
+                continue;
+            }
+        }
+
+        let mut pending = match &mesh {
+            Some(mesh) => render::render_mesh_deferred(&gpu, shader_source, &options, mesh),
+            None => render::render_geometry_deferred(&gpu, shader_source, &options, cli.geometry),
+        };
+        let gpu_timer = pending
+            .as_mut()
+            .ok()
+            .and_then(render::PendingReadback::take_gpu_timer);
+        let data = if cli.poll_readback {
+            pending.and_then(|pending| {
+                std::thread::scope(|scope| {
+                    scope.spawn(|| gpu.device.poll(wgpu::Maintain::Wait));
+                    pollster::block_on(pending.receive(&gpu.device))
+                })
+            })
+        } else {
+            pending.and_then(|pending| pending.wait_for(&gpu.device, options.timeout))
+        };
+
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!("{e}");
+                println!("[fail] {}", variant_defs.join(" "));
+                failed += 1;
+                continue;
+            }
+        };
+
+        // The pixels being back means the timestamps are too, this doesn't block.
+        if let Some(gpu_timer) = gpu_timer {
+            match gpu_timer.wait(&gpu.device) {
+                Ok(elapsed) => println!(
+                    "render pass took {:.1} µs on the GPU",
+                    elapsed.as_secs_f64() * 1e6
+                ),
+                Err(e) => log::error!("failed to read back the timestamps: {e}"),
+            }
+        }
+
+        let variant_defs: Vec<&str> = variant_defs.iter().map(String::as_str).collect();
+        let metadata = if cli.deterministic {
+            output::ImageMetadata::deterministic(shader_path, &variant_defs)
+        } else {
+            output::ImageMetadata::new(shader_path, &variant_defs)
+        };
+        // The layers of the first color target, then those of the next.
+        let layer_size = data.len() / (options.color_targets * options.layers) as usize;
+        for (index, data) in data.chunks_exact(layer_size).enumerate() {
+            let (target, layer) = (
+                index / options.layers as usize,
+                index % options.layers as usize,
+            );
+            let mut suffix = String::new();
+            if options.color_targets > 1 {
+                suffix.push_str(&format!("_{target}"));
+            }
+            if options.layers > 1 {
+                suffix.push_str(&format!("_layer{layer}"));
+            }
+            let layer_path = output::with_suffix(&output_path, &suffix);
+            output::save_image(
+                &layer_path,
+                options.size.0,
+                options.size.1,
+                options.format,
+                data.to_vec(),
+                cli.encode_srgb,
+                &metadata,
+            )
+            .map_err(|e| format!("failed to save {}: {e}", layer_path.display()))?;
+        }
+
+        if !variant.is_empty() {
+            println!(
+                "[ok]   {} -> {}",
+                variant_defs.join(" "),
+                output_path.display()
+            );
+        }
+    }
+
+    if cli.profile_memory {
+        println!("{}", shader_maker.memory_report());
+    }
+
+    match failed {
+        0 => Ok(()),
+        1 => Err("a variant failed".into()),
+        failed => Err(format!("{failed} variants failed").into()),
+    }
 }
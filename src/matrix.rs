@@ -0,0 +1,103 @@
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga_oil::compose::{Composer, NagaModuleDescriptor, ShaderDefValue};
+use std::collections::HashMap;
+
+/// Capability sets roughly matching what different backends can offer.
+fn capability_sets() -> Vec<(&'static str, Capabilities)> {
+    vec![
+        ("none", Capabilities::empty()),
+        ("push_constant", Capabilities::PUSH_CONSTANT),
+        ("float64", Capabilities::FLOAT64),
+        ("multiview", Capabilities::MULTIVIEW),
+        (
+            "desktop",
+            Capabilities::PUSH_CONSTANT
+                | Capabilities::FLOAT64
+                | Capabilities::PRIMITIVE_INDEX
+                | Capabilities::CLIP_DISTANCE
+                | Capabilities::CULL_DISTANCE
+                | Capabilities::MULTIVIEW
+                | Capabilities::EARLY_DEPTH_TEST
+                | Capabilities::CUBE_ARRAY_TEXTURES,
+        ),
+        ("all", Capabilities::all()),
+    ]
+}
+
+/// Validation flag sets, from the strict default down to almost nothing.
+fn validation_sets() -> Vec<(&'static str, ValidationFlags)> {
+    vec![
+        ("all", ValidationFlags::all()),
+        (
+            "no_uniformity",
+            ValidationFlags::all() - ValidationFlags::CONTROL_FLOW_UNIFORMITY,
+        ),
+        (
+            "no_bindings",
+            ValidationFlags::all() - ValidationFlags::BINDINGS,
+        ),
+        ("minimal", ValidationFlags::empty()),
+    ]
+}
+
+/// Join an error and all of its sources into one line.
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        message.push_str(&format!(": {e}"));
+        source = e.source();
+    }
+    message
+}
+
+/// Compose a shader once per capability set and validate it with every flag set,
+/// printing the outcome of each combination and whether any of them disagree.
+///
+/// Returns `false` if at least one combination failed.
+pub fn run(source: &str, shader_defs: &HashMap<String, ShaderDefValue>) -> bool {
+    let mut outcomes = Vec::new();
+
+    for (caps_name, caps) in capability_sets() {
+        // Validation happens below, so the composer only has to build the module.
+        let mut composer = Composer::non_validating().with_capabilities(caps);
+
+        let module = composer.make_naga_module(NagaModuleDescriptor {
+            source,
+            shader_defs: shader_defs.clone(),
+            ..Default::default()
+        });
+
+        for (flags_name, flags) in validation_sets() {
+            let outcome = match &module {
+                Ok(module) => match Validator::new(flags, caps).validate(module) {
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(format!("validation: {}", error_chain(e.as_inner()))),
+                },
+                Err(e) => Err(format!("compose: {}", e.inner)),
+            };
+
+            match &outcome {
+                Ok(()) => println!("[ok]   caps={caps_name:<14} flags={flags_name}"),
+                Err(e) => println!("[fail] caps={caps_name:<14} flags={flags_name} -> {e}"),
+            }
+
+            outcomes.push((caps_name, flags_name, outcome));
+        }
+    }
+
+    let passed = outcomes.iter().filter(|(_, _, o)| o.is_ok()).count();
+    if passed == outcomes.len() {
+        println!("All {} combinations passed", outcomes.len());
+    } else if passed == 0 {
+        println!("All {} combinations failed", outcomes.len());
+    } else {
+        println!(
+            "Outcome depends on capabilities/flags: {} of {} combinations passed",
+            passed,
+            outcomes.len()
+        );
+    }
+
+    passed == outcomes.len()
+}
@@ -0,0 +1,162 @@
+use crate::render::{Mesh, Vertex3d};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Resolve a 1-based OBJ index, negative ones counting back from the last element so far.
+fn resolve(index: &str, count: usize, line: usize) -> Result<usize, String> {
+    let index: i64 = index
+        .parse()
+        .map_err(|_| format!("line {line}: invalid index `{index}`"))?;
+
+    let resolved = match index {
+        1.. => index - 1,
+        ..=-1 => count as i64 + index,
+        0 => -1,
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "line {line}: index {index} is out of range, there are {count}"
+        ));
+    }
+
+    Ok(resolved as usize)
+}
+
+fn parse_floats<const N: usize>(values: &[&str], line: usize) -> Result<[f32; N], String> {
+    let mut floats = [0.0; N];
+    if values.len() < N {
+        return Err(format!("line {line}: expected {N} numbers"));
+    }
+    for (float, value) in floats.iter_mut().zip(values) {
+        *float = value
+            .parse()
+            .map_err(|_| format!("line {line}: invalid number `{value}`"))?;
+    }
+
+    Ok(floats)
+}
+
+/// Load the positions, normals, uvs and faces of a Wavefront OBJ file into a mesh.
+///
+/// Faces of more than three vertices are split into a fan of triangles, which is only
+/// right for convex ones. Vertices without a normal get the average of the normals of
+/// the faces around them, and uvs are flipped to have (0, 0) at the top left like the
+/// built-in meshes. Materials, groups and everything else are ignored.
+pub fn load(path: &Path) -> Result<Mesh<Vertex3d>, String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let error = |e: String| format!("{}: {e}", path.display());
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    // Every distinct position/uv/normal combination becomes one vertex.
+    let mut vertex_indices: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+    let mut missing_normals = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let values: Vec<&str> = words.collect();
+
+        match keyword {
+            "v" => positions.push(parse_floats::<3>(&values, number).map_err(error)?),
+            "vn" => normals.push(parse_floats::<3>(&values, number).map_err(error)?),
+            "vt" => {
+                let [u, v] = parse_floats::<2>(&values, number).map_err(error)?;
+                uvs.push([u, 1.0 - v]);
+            }
+            "f" => {
+                if values.len() < 3 {
+                    return Err(error(format!(
+                        "line {number}: a face needs at least three vertices"
+                    )));
+                }
+
+                let mut face = Vec::new();
+                for value in values {
+                    let mut parts = value.split('/');
+                    let position = resolve(parts.next().unwrap_or(""), positions.len(), number);
+                    let uv = parts
+                        .next()
+                        .filter(|uv| !uv.is_empty())
+                        .map(|uv| resolve(uv, uvs.len(), number))
+                        .transpose();
+                    let normal = parts
+                        .next()
+                        .map(|normal| resolve(normal, normals.len(), number))
+                        .transpose();
+                    let key = (
+                        position.map_err(error)?,
+                        uv.map_err(error)?,
+                        normal.map_err(error)?,
+                    );
+
+                    let index = *vertex_indices.entry(key).or_insert_with(|| {
+                        let (position, uv, normal) = key;
+                        if normal.is_none() {
+                            missing_normals.push(vertices.len());
+                        }
+                        vertices.push(Vertex3d {
+                            position: positions[position],
+                            normal: normal.map_or([0.0; 3], |normal| normals[normal]),
+                            uv: uv.map_or([0.0; 2], |uv| uvs[uv]),
+                        });
+                        vertices.len() as u32 - 1
+                    });
+                    face.push(index);
+                }
+
+                for i in 1..face.len() - 1 {
+                    indices.extend([face[0], face[i], face[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if indices.is_empty() {
+        return Err(error("no faces to draw".into()));
+    }
+
+    if !missing_normals.is_empty() {
+        let mut accumulated = vec![[0.0f32; 3]; vertices.len()];
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize].position);
+            let (u, v) = (
+                [b[0] - a[0], b[1] - a[1], b[2] - a[2]],
+                [c[0] - a[0], c[1] - a[1], c[2] - a[2]],
+            );
+            // Not normalized, so bigger faces weigh more.
+            let normal = [
+                u[1] * v[2] - u[2] * v[1],
+                u[2] * v[0] - u[0] * v[2],
+                u[0] * v[1] - u[1] * v[0],
+            ];
+            for &index in triangle {
+                for (sum, n) in accumulated[index as usize].iter_mut().zip(normal) {
+                    *sum += n;
+                }
+            }
+        }
+
+        for index in missing_normals {
+            let [x, y, z] = accumulated[index];
+            let length = (x * x + y * y + z * z).sqrt();
+            if length > 0.0 {
+                vertices[index].normal = [x / length, y / length, z / length];
+            }
+        }
+    }
+
+    Ok(Mesh {
+        vertices,
+        indices: indices.into(),
+    })
+}
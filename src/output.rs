@@ -0,0 +1,175 @@
+use crate::render::{self, RenderError};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Provenance of a rendered image, stored in the PNG itself.
+pub struct ImageMetadata {
+    pub shader_path: String,
+    pub shader_defs: Vec<String>,
+    /// Seconds since the Unix epoch, left out for byte-stable output.
+    pub timestamp: Option<u64>,
+}
+
+impl ImageMetadata {
+    /// Record the shader and defs used for a render happening now.
+    pub fn new(shader_path: &str, shader_defs: &[&str]) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Self {
+            shader_path: shader_path.into(),
+            shader_defs: shader_defs.iter().map(|def| (*def).into()).collect(),
+            timestamp: Some(timestamp),
+        }
+    }
+
+    /// Like `new`, but without a timestamp so identical renders give identical files.
+    pub fn deterministic(shader_path: &str, shader_defs: &[&str]) -> Self {
+        Self {
+            timestamp: None,
+            ..Self::new(shader_path, shader_defs)
+        }
+    }
+}
+
+/// Save tightly packed RGBA8 pixels as a PNG, embedding the metadata as tEXt chunks.
+///
+/// Compression and filtering are pinned rather than left to the encoder's defaults, so
+/// the same pixels and metadata always encode to the same bytes with a given `png` version.
+pub fn save_png_with_metadata(
+    path: &Path,
+    width: u32,
+    height: u32,
+    data: &[u8],
+    metadata: &ImageMetadata,
+) -> Result<(), png::EncodingError> {
+    let file = File::create(path)?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Default);
+    encoder.set_filter(png::FilterType::Sub);
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::NonAdaptive);
+
+    encoder.add_text_chunk("Shader".into(), metadata.shader_path.clone())?;
+    encoder.add_text_chunk("Shader Defs".into(), metadata.shader_defs.join(","))?;
+    if let Some(timestamp) = metadata.timestamp {
+        encoder.add_text_chunk("Timestamp".into(), timestamp.to_string())?;
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Png(png::EncodingError),
+    Image(image::ImageError),
+    /// The pixels couldn't be converted for the file type.
+    Render(RenderError),
+    /// No encoder for the file extension.
+    UnsupportedExtension(String),
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::Png(e) => write!(f, "{e}"),
+            SaveError::Image(e) => write!(f, "{e}"),
+            SaveError::Render(e) => write!(f, "{e}"),
+            SaveError::UnsupportedExtension(extension) => write!(
+                f,
+                "can't save `.{extension}` files, use .png, .jpg, .bmp or .exr"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<png::EncodingError> for SaveError {
+    fn from(e: png::EncodingError) -> Self {
+        SaveError::Png(e)
+    }
+}
+
+impl From<image::ImageError> for SaveError {
+    fn from(e: image::ImageError) -> Self {
+        SaveError::Image(e)
+    }
+}
+
+impl From<RenderError> for SaveError {
+    fn from(e: RenderError) -> Self {
+        SaveError::Render(e)
+    }
+}
+
+/// Save pixels read back from a `format` target in the file type of the path's extension,
+/// PNG without one.
+///
+/// PNG, JPEG and BMP get 8 bits per channel, JPEG without alpha, and with `encode_srgb`
+/// the values of linear targets are sRGB encoded for them. OpenEXR keeps float targets'
+/// full range as 32-bit float channels, always linear. Only PNGs carry the metadata.
+pub fn save_image(
+    path: &Path,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    data: Vec<u8>,
+    encode_srgb: bool,
+    metadata: &ImageMetadata,
+) -> Result<(), SaveError> {
+    let extension = path.extension().map_or("png".into(), |extension| {
+        extension.to_string_lossy().to_lowercase()
+    });
+    let to_8bit = |data| {
+        if encode_srgb {
+            render::to_srgb8(format, data)
+        } else {
+            render::to_rgba8(format, data)
+        }
+    };
+    let rgba8 = |data| {
+        let data = to_8bit(data)?;
+        Ok::<_, SaveError>(
+            image::RgbaImage::from_raw(width, height, data)
+                .expect("readback returns tightly packed rows of the target size"),
+        )
+    };
+
+    match extension.as_str() {
+        "png" => save_png_with_metadata(path, width, height, &to_8bit(data)?, metadata)?,
+        "jpg" | "jpeg" => image::DynamicImage::ImageRgba8(rgba8(data)?)
+            .to_rgb8()
+            .save(path)?,
+        "bmp" => rgba8(data)?.save(path)?,
+        "exr" => image::Rgba32FImage::from_raw(width, height, render::to_rgba32f(format, &data)?)
+            .expect("readback returns tightly packed rows of the target size")
+            .save(path)?,
+        _ => return Err(SaveError::UnsupportedExtension(extension)),
+    }
+
+    Ok(())
+}
+
+/// `path` with `suffix` added to the file name before its extension.
+pub fn with_suffix(path: &Path, suffix: &str) -> std::path::PathBuf {
+    let mut file_name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    file_name.push_str(suffix);
+    if let Some(extension) = path.extension() {
+        file_name.push_str(&format!(".{}", extension.to_string_lossy()));
+    }
+
+    path.with_file_name(file_name)
+}
@@ -0,0 +1,188 @@
+use crate::render::{self, FrameUniforms, Gpu, GpuError, GpuOptions, RenderOptions};
+use wgpu::util::DeviceExt;
+use winit::event::{Event, WindowEvent};
+use winit::event_loop::EventLoop;
+use winit::window::WindowBuilder;
+
+/// Open a window and draw the shader into it every frame until the window is closed.
+///
+/// The pipeline is the same fullscreen quad the offscreen renders use, built for the
+/// surface's format. Resizing reconfigures the surface, the zero size of a minimized
+/// window is ignored. With `frame_uniforms`, the time since the window opened and its
+/// size are updated every frame.
+pub fn run(
+    shader_source: wgpu::ShaderSource<'static>,
+    size: (u32, u32),
+    frame_uniforms: bool,
+    options: &GpuOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let event_loop = EventLoop::new()?;
+    let window = WindowBuilder::new()
+        .with_title("naga_oil preview")
+        .with_inner_size(winit::dpi::PhysicalSize::new(size.0, size.1))
+        .build(&event_loop)?;
+
+    let connect = |options: &GpuOptions| -> Result<_, Box<dyn std::error::Error>> {
+        let instance = options.instance();
+        // SAFETY: `window` is declared first, so it outlives the surface.
+        let surface = unsafe { instance.create_surface(&window) }?;
+        let gpu = Gpu::for_surface(&instance, &surface, options)?;
+        Ok((surface, gpu))
+    };
+    let (surface, gpu) = match (connect(options), options.backend) {
+        (Err(e), Some(backend)) if e.is::<GpuError>() => {
+            log::warn!("{e} on {backend:?}, falling back to any backend");
+            connect(&GpuOptions {
+                backend: None,
+                ..*options
+            })?
+        }
+        (result, _) => result?,
+    };
+    let device = &gpu.device;
+
+    let capabilities = surface.get_capabilities(&gpu.adapter);
+    let (Some(&format), Some(&alpha_mode)) = (
+        capabilities.formats.first(),
+        capabilities.alpha_modes.first(),
+    ) else {
+        return Err("the adapter can't present to the window's surface".into());
+    };
+    let mut config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width: window.inner_size().width.max(1),
+        height: window.inner_size().height.max(1),
+        present_mode: wgpu::PresentMode::Fifo,
+        alpha_mode,
+        view_formats: vec![],
+    };
+    surface.configure(device, &config);
+
+    let frame_binding = frame_uniforms.then(|| {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("frame uniform buffer"),
+            contents: bytemuck::bytes_of(&FrameUniforms::new(0.0, size)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("frame bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("frame bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        (buffer, layout, bind_group)
+    });
+
+    let bind_group_layouts: Vec<&wgpu::BindGroupLayout> =
+        frame_binding.iter().map(|(_, layout, _)| layout).collect();
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+    let pipeline = render::capture_validation(device, || {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: shader_source,
+        });
+        render::quad_pipeline(device, &shader, &layout, format, &RenderOptions::default())
+    })?;
+    let (vertex_buffer, index_buffer) = render::quad_buffers(device);
+
+    let mut error: Option<wgpu::SurfaceError> = None;
+    let start = std::time::Instant::now();
+
+    event_loop.run(|event, target| match event {
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::CloseRequested => target.exit(),
+            WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                config.width = size.width;
+                config.height = size.height;
+                surface.configure(device, &config);
+            }
+            WindowEvent::RedrawRequested => {
+                let frame = match surface.get_current_texture() {
+                    Ok(frame) => frame,
+                    // Gone stale, e.g. from a resize still in flight, so try again next frame.
+                    Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                        surface.configure(device, &config);
+                        return;
+                    }
+                    Err(wgpu::SurfaceError::Timeout) => return,
+                    Err(e) => {
+                        error = Some(e);
+                        target.exit();
+                        return;
+                    }
+                };
+                let view = frame
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+
+                if let Some((buffer, _, _)) = &frame_binding {
+                    let uniforms = FrameUniforms::new(
+                        start.elapsed().as_secs_f32(),
+                        (config.width, config.height),
+                    );
+                    gpu.queue
+                        .write_buffer(buffer, 0, bytemuck::bytes_of(&uniforms));
+                }
+
+                let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("preview encoder"),
+                });
+                {
+                    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("preview render pass"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(render::CLEAR_COLOR),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+                    render_pass.set_pipeline(&pipeline);
+                    if let Some((_, _, bind_group)) = &frame_binding {
+                        render_pass.set_bind_group(0, bind_group, &[]);
+                    }
+                    render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    render_pass.draw_indexed(0..6, 0, 0..1);
+                }
+                gpu.queue.submit(Some(encoder.finish()));
+                frame.present();
+            }
+            _ => {}
+        },
+        // Draw continuously so animated shaders keep moving.
+        Event::AboutToWait => window.request_redraw(),
+        _ => {}
+    })?;
+
+    match error {
+        Some(e) => Err(format!("failed to get the next frame: {e}").into()),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,467 @@
+use naga::{
+    AddressSpace, ArraySize, Handle, ImageClass, ImageDimension, ScalarKind, Type, TypeInner,
+};
+
+/// What naga_oil wraps the base32 encoded module name of imported items in.
+const DECORATION_PRE: &str = "X_naga_oil_mod_X";
+const DECORATION_POST: &str = "X";
+
+/// Turn a naga_oil decorated name like `fooX_naga_oil_mod_XNVXWIX` back into `mod::foo`.
+pub fn undecorate(name: &str) -> String {
+    let Some((item, rest)) = name.split_once(DECORATION_PRE) else {
+        return name.into();
+    };
+
+    rest.strip_suffix(DECORATION_POST)
+        .and_then(decode_base32)
+        .map(|module| format!("{module}::{item}"))
+        .unwrap_or_else(|| name.into())
+}
+
+/// Decode unpadded RFC 4648 base32, the encoding naga_oil uses for module names.
+fn decode_base32(encoded: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+
+    for c in encoded.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+fn scalar_name(kind: ScalarKind, width: u8) -> String {
+    match kind {
+        ScalarKind::Sint => format!("i{}", width * 8),
+        ScalarKind::Uint => format!("u{}", width * 8),
+        ScalarKind::Float => format!("f{}", width * 8),
+        ScalarKind::Bool => "bool".into(),
+    }
+}
+
+fn storage_access_name(access: naga::StorageAccess) -> &'static str {
+    if access == naga::StorageAccess::LOAD {
+        "read"
+    } else if access == naga::StorageAccess::STORE {
+        "write"
+    } else {
+        "read_write"
+    }
+}
+
+/// Format a type the way it would be spelled in WGSL, as far as naga keeps the information.
+pub fn type_name(module: &naga::Module, ty: Handle<Type>) -> String {
+    let ty = &module.types[ty];
+
+    if let Some(name) = &ty.name {
+        return undecorate(name);
+    }
+
+    match &ty.inner {
+        TypeInner::Scalar { kind, width } => scalar_name(*kind, *width),
+        TypeInner::Vector { size, kind, width } => {
+            format!("vec{}<{}>", *size as u8, scalar_name(*kind, *width))
+        }
+        TypeInner::Matrix {
+            columns,
+            rows,
+            width,
+        } => format!(
+            "mat{}x{}<{}>",
+            *columns as u8,
+            *rows as u8,
+            scalar_name(ScalarKind::Float, *width)
+        ),
+        TypeInner::Atomic { kind, width } => format!("atomic<{}>", scalar_name(*kind, *width)),
+        TypeInner::Pointer { base, space } => {
+            format!("ptr<{space:?}, {}>", type_name(module, *base))
+        }
+        TypeInner::ValuePointer {
+            size,
+            kind,
+            width,
+            space,
+        } => {
+            let pointee = match size {
+                Some(size) => format!("vec{}<{}>", *size as u8, scalar_name(*kind, *width)),
+                None => scalar_name(*kind, *width),
+            };
+            format!("ptr<{space:?}, {pointee}>")
+        }
+        TypeInner::Array { base, size, .. } => match size {
+            ArraySize::Constant(size) => format!("array<{}, {size}>", type_name(module, *base)),
+            ArraySize::Dynamic => format!("array<{}>", type_name(module, *base)),
+        },
+        TypeInner::BindingArray { base, size } => match size {
+            ArraySize::Constant(size) => {
+                format!("binding_array<{}, {size}>", type_name(module, *base))
+            }
+            ArraySize::Dynamic => format!("binding_array<{}>", type_name(module, *base)),
+        },
+        TypeInner::Image {
+            dim,
+            arrayed,
+            class,
+        } => {
+            let dim = match dim {
+                ImageDimension::D1 => "1d",
+                ImageDimension::D2 => "2d",
+                ImageDimension::D3 => "3d",
+                ImageDimension::Cube => "cube",
+            };
+            let array = if *arrayed { "_array" } else { "" };
+            match class {
+                ImageClass::Sampled { kind, multi } => format!(
+                    "texture_{}{dim}{array}<{}>",
+                    if *multi { "multisampled_" } else { "" },
+                    scalar_name(*kind, 4)
+                ),
+                ImageClass::Depth { multi: false } => format!("texture_depth_{dim}{array}"),
+                ImageClass::Depth { multi: true } => format!("texture_depth_multisampled_{dim}"),
+                ImageClass::Storage { format, access } => format!(
+                    "texture_storage_{dim}{array}<{}, {}>",
+                    format!("{format:?}").to_lowercase(),
+                    storage_access_name(*access)
+                ),
+            }
+        }
+        TypeInner::Sampler { comparison: false } => "sampler".into(),
+        TypeInner::Sampler { comparison: true } => "sampler_comparison".into(),
+        // Anonymous structs and the remaining types rarely show up in signatures.
+        other => format!("{other:?}"),
+    }
+}
+
+/// `fn name(arg: type, ...) -> type` for a function of the module.
+fn signature(module: &naga::Module, name: &str, function: &naga::Function) -> String {
+    let arguments: Vec<String> = function
+        .arguments
+        .iter()
+        .enumerate()
+        .map(|(index, argument)| {
+            let name = argument
+                .name
+                .as_deref()
+                .map(undecorate)
+                .unwrap_or_else(|| format!("_{index}"));
+            format!("{name}: {}", type_name(module, argument.ty))
+        })
+        .collect();
+
+    let mut signature = format!("fn {}({})", undecorate(name), arguments.join(", "));
+    if let Some(result) = &function.result {
+        signature.push_str(&format!(" -> {}", type_name(module, result.ty)));
+    }
+
+    signature
+}
+
+/// Signatures of every function in the module, entry points last and marked by their stage.
+///
+/// Functions that came from imports are listed as `module::function`.
+pub fn function_signatures(module: &naga::Module) -> Vec<String> {
+    let functions = module.functions.iter().map(|(_, function)| {
+        let name = function.name.as_deref().unwrap_or("<unnamed>");
+        signature(module, name, function)
+    });
+
+    let entry_points = module.entry_points.iter().map(|entry_point| {
+        let stage = match entry_point.stage {
+            naga::ShaderStage::Vertex => "@vertex",
+            naga::ShaderStage::Fragment => "@fragment",
+            naga::ShaderStage::Compute => "@compute",
+        };
+        format!(
+            "{stage} {}",
+            signature(module, &entry_point.name, &entry_point.function)
+        )
+    });
+
+    functions.chain(entry_points).collect()
+}
+
+/// Color targets a fragment entry point writes, one past the highest `@location` of its
+/// outputs, 0 for one that only writes depth or nothing at all.
+pub fn color_outputs(module: &naga::Module, entry_point: &naga::EntryPoint) -> u32 {
+    let Some(result) = &entry_point.function.result else {
+        return 0;
+    };
+    // The second source of dual-source blending goes to the same target as the first.
+    let target_count = |binding: &Option<naga::Binding>| match binding {
+        Some(naga::Binding::Location {
+            location,
+            second_blend_source: false,
+            ..
+        }) => Some(location + 1),
+        _ => None,
+    };
+
+    match &module.types[result.ty].inner {
+        TypeInner::Struct { members, .. } => members
+            .iter()
+            .filter_map(|member| target_count(&member.binding))
+            .max()
+            .unwrap_or(0),
+        _ => target_count(&result.binding).unwrap_or(0),
+    }
+}
+
+/// A resource the shader binds, as reflected from its global variables.
+#[derive(Clone, Debug)]
+pub struct BindingInfo {
+    pub group: u32,
+    pub binding: u32,
+    /// Variable name, `module::name` for those that came from imports.
+    pub name: Option<String>,
+    /// WGSL spelling of the variable's type.
+    pub ty: String,
+    /// Layout type a matching bind group layout entry needs.
+    pub binding_type: wgpu::BindingType,
+    /// Element count of a `binding_array`.
+    pub count: Option<std::num::NonZeroU32>,
+    /// Stages of the entry points that use the resource.
+    pub visibility: wgpu::ShaderStages,
+}
+
+impl BindingInfo {
+    /// The bind group layout entry for this resource.
+    pub fn layout_entry(&self) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding: self.binding,
+            visibility: self.visibility,
+            ty: self.binding_type,
+            count: self.count,
+        }
+    }
+}
+
+impl std::fmt::Display for BindingInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let space = match self.binding_type {
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                ..
+            } => "<uniform>",
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                ..
+            } => "<storage, read>",
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                ..
+            } => "<storage, read_write>",
+            _ => "",
+        };
+        write!(
+            f,
+            "@group({}) @binding({}) var{space} {}: {}",
+            self.group,
+            self.binding,
+            self.name.as_deref().unwrap_or("_"),
+            self.ty
+        )?;
+
+        let stages: Vec<&str> = [
+            (wgpu::ShaderStages::VERTEX, "vertex"),
+            (wgpu::ShaderStages::FRAGMENT, "fragment"),
+            (wgpu::ShaderStages::COMPUTE, "compute"),
+        ]
+        .into_iter()
+        .filter(|(stage, _)| self.visibility.contains(*stage))
+        .map(|(_, name)| name)
+        .collect();
+        if stages.is_empty() {
+            write!(f, "  // unused")
+        } else {
+            write!(f, "  // {}", stages.join(", "))
+        }
+    }
+}
+
+fn storage_format(format: naga::StorageFormat) -> wgpu::TextureFormat {
+    use naga::StorageFormat as Sf;
+    use wgpu::TextureFormat as Tf;
+
+    match format {
+        Sf::R8Unorm => Tf::R8Unorm,
+        Sf::R8Snorm => Tf::R8Snorm,
+        Sf::R8Uint => Tf::R8Uint,
+        Sf::R8Sint => Tf::R8Sint,
+        Sf::R16Uint => Tf::R16Uint,
+        Sf::R16Sint => Tf::R16Sint,
+        Sf::R16Float => Tf::R16Float,
+        Sf::Rg8Unorm => Tf::Rg8Unorm,
+        Sf::Rg8Snorm => Tf::Rg8Snorm,
+        Sf::Rg8Uint => Tf::Rg8Uint,
+        Sf::Rg8Sint => Tf::Rg8Sint,
+        Sf::R32Uint => Tf::R32Uint,
+        Sf::R32Sint => Tf::R32Sint,
+        Sf::R32Float => Tf::R32Float,
+        Sf::Rg16Uint => Tf::Rg16Uint,
+        Sf::Rg16Sint => Tf::Rg16Sint,
+        Sf::Rg16Float => Tf::Rg16Float,
+        Sf::Rgba8Unorm => Tf::Rgba8Unorm,
+        Sf::Rgba8Snorm => Tf::Rgba8Snorm,
+        Sf::Rgba8Uint => Tf::Rgba8Uint,
+        Sf::Rgba8Sint => Tf::Rgba8Sint,
+        Sf::Bgra8Unorm => Tf::Bgra8Unorm,
+        Sf::Rgb10a2Uint => Tf::Rgb10a2Uint,
+        Sf::Rgb10a2Unorm => Tf::Rgb10a2Unorm,
+        Sf::Rg11b10Float => Tf::Rg11b10Float,
+        Sf::Rg32Uint => Tf::Rg32Uint,
+        Sf::Rg32Sint => Tf::Rg32Sint,
+        Sf::Rg32Float => Tf::Rg32Float,
+        Sf::Rgba16Uint => Tf::Rgba16Uint,
+        Sf::Rgba16Sint => Tf::Rgba16Sint,
+        Sf::Rgba16Float => Tf::Rgba16Float,
+        Sf::Rgba32Uint => Tf::Rgba32Uint,
+        Sf::Rgba32Sint => Tf::Rgba32Sint,
+        Sf::Rgba32Float => Tf::Rgba32Float,
+        Sf::R16Unorm => Tf::R16Unorm,
+        Sf::R16Snorm => Tf::R16Snorm,
+        Sf::Rg16Unorm => Tf::Rg16Unorm,
+        Sf::Rg16Snorm => Tf::Rg16Snorm,
+        Sf::Rgba16Unorm => Tf::Rgba16Unorm,
+        Sf::Rgba16Snorm => Tf::Rgba16Snorm,
+    }
+}
+
+/// The layout type for a resource of type `ty` in `space`, `None` for types that can't be bound.
+fn binding_type(
+    module: &naga::Module,
+    space: AddressSpace,
+    ty: Handle<Type>,
+) -> Option<wgpu::BindingType> {
+    let buffer = |ty| wgpu::BindingType::Buffer {
+        ty,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+    };
+
+    match space {
+        AddressSpace::Uniform => return Some(buffer(wgpu::BufferBindingType::Uniform)),
+        AddressSpace::Storage { access } => {
+            return Some(buffer(wgpu::BufferBindingType::Storage {
+                read_only: !access.contains(naga::StorageAccess::STORE),
+            }))
+        }
+        AddressSpace::Handle => {}
+        _ => return None,
+    }
+
+    let inner = match &module.types[ty].inner {
+        TypeInner::BindingArray { base, .. } => &module.types[*base].inner,
+        inner => inner,
+    };
+
+    match inner {
+        TypeInner::Sampler { comparison: false } => Some(wgpu::BindingType::Sampler(
+            wgpu::SamplerBindingType::Filtering,
+        )),
+        TypeInner::Sampler { comparison: true } => Some(wgpu::BindingType::Sampler(
+            wgpu::SamplerBindingType::Comparison,
+        )),
+        TypeInner::Image {
+            dim,
+            arrayed,
+            class,
+        } => {
+            let view_dimension = match (dim, arrayed) {
+                (ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+                (ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                (ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                (ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+                (ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                (ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+            };
+            Some(match class {
+                ImageClass::Sampled { kind, multi } => wgpu::BindingType::Texture {
+                    sample_type: match kind {
+                        ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                        ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                        // Multisampled textures can't be filtered.
+                        _ => wgpu::TextureSampleType::Float { filterable: !multi },
+                    },
+                    view_dimension,
+                    multisampled: *multi,
+                },
+                ImageClass::Depth { multi } => wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension,
+                    multisampled: *multi,
+                },
+                ImageClass::Storage { format, access } => wgpu::BindingType::StorageTexture {
+                    access: if *access == naga::StorageAccess::LOAD {
+                        wgpu::StorageTextureAccess::ReadOnly
+                    } else if *access == naga::StorageAccess::STORE {
+                        wgpu::StorageTextureAccess::WriteOnly
+                    } else {
+                        wgpu::StorageTextureAccess::ReadWrite
+                    },
+                    format: storage_format(*format),
+                    view_dimension,
+                },
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Every bound resource of the module, sorted by group and binding.
+///
+/// A resource is visible to the stages of the entry points that `info` says use it.
+/// Samplers are reported as filtering and float textures as filterable, the shader
+/// can't tell whether it will be given something else.
+pub fn bindings(module: &naga::Module, info: &naga::valid::ModuleInfo) -> Vec<BindingInfo> {
+    let mut bindings: Vec<BindingInfo> = module
+        .global_variables
+        .iter()
+        .filter_map(|(handle, variable)| {
+            let resource = variable.binding.as_ref()?;
+            let binding_type = binding_type(module, variable.space, variable.ty)?;
+
+            let count = match module.types[variable.ty].inner {
+                TypeInner::BindingArray {
+                    size: ArraySize::Constant(size),
+                    ..
+                } => Some(size),
+                _ => None,
+            };
+
+            let mut visibility = wgpu::ShaderStages::NONE;
+            for (index, entry_point) in module.entry_points.iter().enumerate() {
+                if !info.get_entry_point(index)[handle].is_empty() {
+                    visibility |= match entry_point.stage {
+                        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+                        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+                        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+                    };
+                }
+            }
+
+            Some(BindingInfo {
+                group: resource.group,
+                binding: resource.binding,
+                name: variable.name.as_deref().map(undecorate),
+                ty: type_name(module, variable.ty),
+                binding_type,
+                count,
+                visibility,
+            })
+        })
+        .collect();
+
+    bindings.sort_by_key(|binding| (binding.group, binding.binding));
+    bindings
+}
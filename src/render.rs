@@ -0,0 +1,2324 @@
+use crate::reflect;
+use crate::shader_maker::ShaderError;
+use crate::stencil::{self, MaskPass, StencilMask};
+use crate::texture::InputTexture;
+use wgpu::util::DeviceExt;
+use wgpu::{BufferAddress, Extent3d, StoreOp};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex2d {
+    pub position: [f32; 2],
+}
+
+/// A vertex with position, normal and texture coordinates for 3D geometry, at shader
+/// locations 0, 1 and 2.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex3d {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+pub trait VertexBuffer: bytemuck::Pod {
+    /// Vertex buffer layout provided to a pipeline.
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
+
+    /// The fullscreen quad, two triangles from (-1, -1) to (1, 1) in clip space.
+    fn quad() -> Mesh<Self>;
+}
+
+/// The built-in meshes a render can draw.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Geometry {
+    /// Fullscreen quad of `Vertex2d`, positions at location 0.
+    #[default]
+    Quad,
+    /// Fullscreen quad of `Vertex3d`, with positions, normals and uvs at locations 0 to 2.
+    Quad3d,
+    /// Unit cube of `Vertex3d`, for shaders with their own projection.
+    Cube,
+    /// A triangle covering the target the vertex shader makes from `@builtin(vertex_index)`,
+    /// without vertex buffers, see `fullscreen.wgsl` and `render_fullscreen`.
+    Fullscreen,
+}
+
+/// Vertices and the triangle list indexing them.
+pub struct Mesh<V> {
+    pub vertices: Vec<V>,
+    pub indices: Indices,
+}
+
+/// Index data of a mesh, 16-bit indices take half the space for up to 65536 vertices.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    pub fn format(&self) -> wgpu::IndexFormat {
+        match self {
+            Indices::U16(_) => wgpu::IndexFormat::Uint16,
+            Indices::U32(_) => wgpu::IndexFormat::Uint32,
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        match self {
+            Indices::U16(indices) => indices.len() as u32,
+            Indices::U32(indices) => indices.len() as u32,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Indices::U16(indices) => bytemuck::cast_slice(indices),
+            Indices::U32(indices) => bytemuck::cast_slice(indices),
+        }
+    }
+
+    /// The same indices in `format`, `None` if one doesn't fit in 16 bits.
+    pub fn to_format(&self, format: wgpu::IndexFormat) -> Option<Self> {
+        match (self, format) {
+            (Indices::U16(indices), wgpu::IndexFormat::Uint32) => Some(Indices::U32(
+                indices.iter().map(|&index| index.into()).collect(),
+            )),
+            (Indices::U32(indices), wgpu::IndexFormat::Uint16) => indices
+                .iter()
+                .map(|&index| u16::try_from(index).ok())
+                .collect::<Option<_>>()
+                .map(Indices::U16),
+            _ => Some(self.clone()),
+        }
+    }
+}
+
+impl From<Vec<u32>> for Indices {
+    fn from(indices: Vec<u32>) -> Self {
+        Indices::U32(indices)
+    }
+}
+
+/// Index format of loaded meshes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum IndexFormat {
+    /// For meshes of up to 65536 vertices.
+    U16,
+    #[default]
+    U32,
+}
+
+impl From<IndexFormat> for wgpu::IndexFormat {
+    fn from(format: IndexFormat) -> Self {
+        match format {
+            IndexFormat::U16 => wgpu::IndexFormat::Uint16,
+            IndexFormat::U32 => wgpu::IndexFormat::Uint32,
+        }
+    }
+}
+
+impl<V: VertexBuffer> Mesh<V> {
+    /// Upload the vertex and index buffers.
+    pub(crate) fn buffers(&self, device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("vertex buffer"),
+            contents: bytemuck::cast_slice(&self.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("index buffer"),
+            contents: self.indices.bytes(),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        (vertex_buffer, index_buffer)
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.indices.count()
+    }
+}
+
+impl VertexBuffer for Vertex2d {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex2d>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[wgpu::VertexAttribute {
+                // Position.
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float32x2,
+            }],
+        }
+    }
+
+    fn quad() -> Mesh<Self> {
+        let vertices = [[-1.0, 1.0], [-1.0, -1.0], [1.0, -1.0], [1.0, 1.0]]
+            .map(|position| Vertex2d { position })
+            .into();
+
+        Mesh {
+            vertices,
+            indices: vec![0, 1, 2, 2, 3, 0].into(),
+        }
+    }
+}
+
+impl VertexBuffer for Vertex3d {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+            wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex3d>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+
+    /// Facing the viewer along +z, with uv (0, 0) at the top left.
+    fn quad() -> Mesh<Self> {
+        let Mesh { vertices, indices } = Vertex2d::quad();
+        let vertices = vertices
+            .into_iter()
+            .map(|Vertex2d { position: [x, y] }| Vertex3d {
+                position: [x, y, 0.0],
+                normal: [0.0, 0.0, 1.0],
+                uv: [(x + 1.0) / 2.0, (1.0 - y) / 2.0],
+            })
+            .collect();
+
+        Mesh { vertices, indices }
+    }
+}
+
+impl Vertex3d {
+    /// A cube from -0.5 to 0.5 on each axis, four vertices per face so every face has its
+    /// own normal and full uv range. Triangles wind counter-clockwise seen from outside.
+    pub fn cube() -> Mesh<Self> {
+        // Normal and the two axes spanning each face, `u` cross `v` pointing along the normal.
+        let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ];
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for (normal, u, v) in faces {
+            let base = vertices.len() as u32;
+            for (s, t) in [(-0.5, 0.5), (-0.5, -0.5), (0.5, -0.5), (0.5, 0.5)] {
+                vertices.push(Vertex3d {
+                    position: std::array::from_fn(|i| normal[i] * 0.5 + u[i] * s + v[i] * t),
+                    normal,
+                    uv: [s + 0.5, 0.5 - t],
+                });
+            }
+            indices.extend([0, 1, 2, 2, 3, 0].map(|index| base + index));
+        }
+
+        Mesh {
+            vertices,
+            indices: indices.into(),
+        }
+    }
+}
+
+/// Shadertoy-style uniforms for `@group(0) @binding(0)`, declared in WGSL as
+/// `struct Frame { resolution: vec2<f32>, time: f32, _pad: f32 }`.
+///
+/// `resolution` comes first so the struct is 16 bytes without hidden padding.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FrameUniforms {
+    /// Render target size in pixels.
+    pub resolution: [f32; 2],
+    /// Seconds since the first frame.
+    pub time: f32,
+    pub _pad: f32,
+}
+
+impl FrameUniforms {
+    pub fn new(time: f32, size: (u32, u32)) -> Self {
+        Self {
+            resolution: [size.0 as f32, size.1 as f32],
+            time,
+            _pad: 0.0,
+        }
+    }
+}
+
+/// Device and queue shared by all renders, and the adapter they were created from.
+pub struct Gpu {
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+/// Graphics APIs an adapter can be requested on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl From<Backend> for wgpu::Backends {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+/// Whether to prefer an integrated or a discrete GPU when there are both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Power {
+    Low,
+    High,
+}
+
+impl From<Power> for wgpu::PowerPreference {
+    fn from(power: Power) -> Self {
+        match power {
+            Power::Low => wgpu::PowerPreference::LowPower,
+            Power::High => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+/// Which adapter to ask wgpu for, by default whatever it picks on any backend.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GpuOptions {
+    pub backend: Option<Backend>,
+    pub power: Option<Power>,
+}
+
+impl GpuOptions {
+    fn backends(&self) -> wgpu::Backends {
+        self.backend.map_or(wgpu::Backends::all(), Into::into)
+    }
+
+    fn power_preference(&self) -> wgpu::PowerPreference {
+        self.power.map(Into::into).unwrap_or_default()
+    }
+
+    /// An instance limited to the requested backend.
+    pub fn instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: self.backends(),
+            ..Default::default()
+        })
+    }
+}
+
+impl Gpu {
+    pub fn new(options: &GpuOptions) -> Result<Self, GpuError> {
+        // Context for all other wgpu objects.
+        let instance = options.instance();
+
+        match (Self::request(&instance, None, options), options.backend) {
+            (Err(GpuError::NoAdapter), Some(backend)) => {
+                log::warn!("No adapter on {backend:?}, falling back to any backend");
+                let any = GpuOptions {
+                    backend: None,
+                    ..*options
+                };
+                Self::request(&any.instance(), None, &any)
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// Pick an adapter that can present to `surface`.
+    ///
+    /// `instance` should come from `options`. There is no fallback to other backends here,
+    /// the surface belongs to the instance, so the caller has to make new ones for that.
+    pub fn for_surface(
+        instance: &wgpu::Instance,
+        surface: &wgpu::Surface,
+        options: &GpuOptions,
+    ) -> Result<Self, GpuError> {
+        Self::request(instance, Some(surface), options)
+    }
+
+    fn request(
+        instance: &wgpu::Instance,
+        compatible_surface: Option<&wgpu::Surface>,
+        options: &GpuOptions,
+    ) -> Result<Self, GpuError> {
+        // Handle to a physical graphics and/or compute device.
+        let request_adapter = |force_fallback_adapter| {
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: options.power_preference(),
+                compatible_surface,
+                force_fallback_adapter,
+            }))
+        };
+        // Machines without a GPU, like many CI runners, may still have a software one.
+        let adapter = match request_adapter(false) {
+            Some(adapter) => adapter,
+            None => {
+                let adapter = request_adapter(true).ok_or(GpuError::NoAdapter)?;
+                log::warn!("No hardware adapter found, rendering in software");
+                adapter
+            }
+        };
+
+        let info = adapter.get_info();
+        log::info!("Using {} ({:?})", info.name, info.backend);
+
+        // Push constants are opt-in, take them and all the space for them the adapter has.
+        // Sample counts beyond the guaranteed 1 and 4 need adapter specific format features.
+        // Timestamp queries are only written when a render is profiled.
+        let features = adapter.features()
+            & (wgpu::Features::PUSH_CONSTANTS
+                | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                | wgpu::Features::TIMESTAMP_QUERY);
+        let limits = device_limits(&adapter.limits());
+        log::info!(
+            "Limits: {} texture size, {} array layers, {} bind groups, {} bytes of push constants",
+            limits.max_texture_dimension_2d,
+            limits.max_texture_array_layers,
+            limits.max_bind_groups,
+            limits.max_push_constant_size
+        );
+        log::debug!("{limits:#?}");
+
+        // Use the adapter to create a device and a queue.
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features,
+                limits,
+                label: None,
+            },
+            None,
+        ))
+        .map_err(GpuError::RequestDevice)?;
+
+        Ok(Self {
+            adapter,
+            device,
+            queue,
+        })
+    }
+
+    /// Whether `format` targets can be multisampled with `count` samples on this device.
+    pub fn supports_sample_count(&self, format: wgpu::TextureFormat, count: u32) -> bool {
+        let features = self.device.features();
+        let format_features =
+            if features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+                self.adapter.get_texture_format_features(format)
+            } else {
+                format.guaranteed_format_features(features)
+            };
+
+        format_features.flags.sample_count_supported(count)
+    }
+}
+
+/// The limits to request a device with on an adapter supporting `adapter`.
+///
+/// The WebGPU defaults are asked for where the adapter has them, falling back to the
+/// downlevel ones and then those of WebGL2, so constrained hardware still gets a device.
+/// An adapter below even those gets exactly what it supports. Texture sizes and push
+/// constants are raised to the adapter's, uniform alignments follow it.
+fn device_limits(adapter: &wgpu::Limits) -> wgpu::Limits {
+    let tiers = [
+        ("default", wgpu::Limits::default()),
+        ("downlevel", wgpu::Limits::downlevel_defaults()),
+        ("WebGL2", wgpu::Limits::downlevel_webgl2_defaults()),
+    ];
+
+    let base = match tiers
+        .into_iter()
+        .find(|(_, tier)| tier.check_limits(adapter))
+    {
+        Some(("default", tier)) => tier,
+        Some((name, tier)) => {
+            log::warn!("The adapter is below the default limits, using the {name} ones");
+            tier
+        }
+        None => {
+            log::warn!("The adapter is below the WebGL2 limits, using its own");
+            adapter.clone()
+        }
+    };
+
+    wgpu::Limits {
+        max_push_constant_size: adapter.max_push_constant_size,
+        ..base
+            .using_resolution(adapter.clone())
+            .using_alignment(adapter.clone())
+    }
+}
+
+#[derive(Debug)]
+pub enum GpuError {
+    /// No adapter matched the request, e.g. because there is no usable GPU or driver, and
+    /// there was no software fallback either.
+    NoAdapter,
+    /// The adapter couldn't create a device with the requested features and limits.
+    RequestDevice(wgpu::RequestDeviceError),
+}
+
+impl std::fmt::Display for GpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GpuError::NoAdapter => write!(
+                f,
+                "no suitable GPU adapter found, not even a software fallback, check the graphics drivers"
+            ),
+            GpuError::RequestDevice(e) => write!(f, "failed to create a device: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for GpuError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GpuError::NoAdapter => None,
+            GpuError::RequestDevice(e) => Some(e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    /// The pipeline was made for a different sample count than an attachment of the pass
+    /// has, e.g. `"depth/stencil"`.
+    SampleCountMismatch {
+        attachment: &'static str,
+        pipeline: u32,
+        target: u32,
+    },
+    /// The blend state uses a constant factor but no blend constant was given.
+    MissingBlendConstant,
+    /// Exact-fit readback was forced for rows that aren't aligned for a buffer copy.
+    UnalignedRows { bytes_per_row: u32 },
+    /// The staging buffer couldn't be mapped for reading.
+    Map(wgpu::BufferAsyncError),
+    /// More push constant data than `Limits::max_push_constant_size` allows.
+    PushConstantsTooLarge { size: u32, limit: u32 },
+    /// The render target format can't be read back, or not converted to RGBA8.
+    UnsupportedFormat(wgpu::TextureFormat),
+    /// A chain was given no passes.
+    EmptyChain,
+    /// The first pass of a chain was set to read a previous one.
+    NoPreviousPass,
+    /// The shader has no entry point of that name for the stage, `available` are those it has.
+    MissingEntryPoint {
+        stage: naga::ShaderStage,
+        name: String,
+        available: Vec<String>,
+    },
+    /// A profiled render on a device without `Features::TIMESTAMP_QUERY`.
+    TimestampsUnsupported,
+    /// A render without a fragment stage was asked for without a depth test to write to.
+    DepthOnlyWithoutDepthTest,
+    /// A render without a fragment stage was given a stencil mask, which isn't supported.
+    DepthOnlyWithStencilMask,
+    /// The readback didn't finish within the timeout, the GPU may be hung on the shader.
+    Timeout(std::time::Duration),
+    /// No layers, or more than `Limits::max_texture_array_layers` allows.
+    InvalidLayers { layers: u32, limit: u32 },
+    /// The fragment shader writes a different number of color targets than were given.
+    ColorTargetMismatch { targets: u32, outputs: u32 },
+    /// wgpu rejected the shader module or the pipeline.
+    Shader(ShaderError),
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::SampleCountMismatch {
+                attachment,
+                pipeline,
+                target,
+            } => write!(
+                f,
+                "pipeline sample count {pipeline} doesn't match the {target} of the {attachment} attachment"
+            ),
+            RenderError::MissingBlendConstant => write!(
+                f,
+                "blend state uses a constant blend factor but no blend constant was set"
+            ),
+            RenderError::UnalignedRows { bytes_per_row } => write!(
+                f,
+                "exact-fit readback needs rows to be a multiple of {} bytes, got {bytes_per_row}",
+                wgpu::COPY_BYTES_PER_ROW_ALIGNMENT
+            ),
+            RenderError::Map(e) => write!(f, "failed to map the staging buffer: {e}"),
+            RenderError::PushConstantsTooLarge { size, limit: 0 } => write!(
+                f,
+                "{size} bytes of push constants were given, but the device doesn't support push constants"
+            ),
+            RenderError::PushConstantsTooLarge { size, limit } => write!(
+                f,
+                "push constants are {size} bytes, {} more than the device's max_push_constant_size of {limit}",
+                size - limit
+            ),
+            RenderError::UnsupportedFormat(format) => {
+                write!(f, "can't read back a {format:?} render target as RGBA8")
+            }
+            RenderError::EmptyChain => write!(f, "a render chain needs at least one pass"),
+            RenderError::NoPreviousPass => {
+                write!(f, "the first pass of a render chain has no previous pass to read")
+            }
+            RenderError::MissingEntryPoint {
+                stage,
+                name,
+                available,
+            } => {
+                write!(f, "shader has no {stage:?} entry point named `{name}`")?;
+                if available.is_empty() {
+                    write!(f, ", it has none for that stage")
+                } else {
+                    write!(f, ", it has `{}`", available.join("`, `"))
+                }
+            }
+            RenderError::TimestampsUnsupported => {
+                write!(f, "the device doesn't support timestamp queries, can't profile the render")
+            }
+            RenderError::DepthOnlyWithoutDepthTest => write!(
+                f,
+                "drawing without a fragment stage only writes depth, which needs a depth test"
+            ),
+            RenderError::DepthOnlyWithStencilMask => {
+                write!(f, "drawing without a fragment stage can't use a stencil mask")
+            }
+            RenderError::Timeout(timeout) => write!(f, "GPU readback timed out after {timeout:?}"),
+            RenderError::InvalidLayers { layers, limit } => write!(
+                f,
+                "can't render {layers} layers, the device supports 1 to {limit}"
+            ),
+            RenderError::ColorTargetMismatch { targets, outputs } => write!(
+                f,
+                "the fragment shader writes {outputs} color targets, the render has {targets}"
+            ),
+            RenderError::Shader(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<ShaderError> for RenderError {
+    fn from(error: ShaderError) -> Self {
+        RenderError::Shader(error)
+    }
+}
+
+/// Run `create`, e.g. making a shader module and its pipeline, and return the first
+/// validation error wgpu raised meanwhile instead of letting it reach the device's
+/// uncaptured error handler, which panics.
+pub fn capture_validation<T>(
+    device: &wgpu::Device,
+    create: impl FnOnce() -> T,
+) -> Result<T, ShaderError> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = create();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(ShaderError::Wgpu(Box::new(error))),
+        None => Ok(value),
+    }
+}
+
+/// How rows of the render target are laid out when copied to the staging buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReadbackAlignment {
+    /// Exact-fit when the row size allows it, padded otherwise.
+    #[default]
+    Auto,
+    /// Always pad rows to `COPY_BYTES_PER_ROW_ALIGNMENT` and strip the padding afterwards.
+    Padded,
+    /// Copy rows without padding, only possible when they are already aligned.
+    Exact,
+}
+
+impl ReadbackAlignment {
+    /// Bytes per staging buffer row for rows of `unpadded` bytes, and whether the
+    /// padding has to be stripped after mapping.
+    pub fn bytes_per_row(self, unpadded: u32) -> Result<(u32, bool), RenderError> {
+        let aligned = unpadded.is_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        match self {
+            ReadbackAlignment::Auto if aligned => Ok((unpadded, false)),
+            ReadbackAlignment::Auto | ReadbackAlignment::Padded => {
+                Ok((align_up(unpadded, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT), true))
+            }
+            ReadbackAlignment::Exact if aligned => Ok((unpadded, false)),
+            ReadbackAlignment::Exact => Err(RenderError::UnalignedRows {
+                bytes_per_row: unpadded,
+            }),
+        }
+    }
+}
+
+/// Render target formats that can be read back as an RGBA8 or RGBA32F image.
+///
+/// The shader writes linear values either way. `Unorm` targets store them as they are,
+/// which image viewers take for sRGB encoded values, so the saved image looks darker than
+/// it should. `Srgb` targets encode them on write, which is what viewers expect.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImageFormat {
+    #[default]
+    Rgba8Unorm,
+    Rgba8UnormSrgb,
+    Bgra8Unorm,
+    Bgra8UnormSrgb,
+    Rgba16Float,
+    Rgba32Float,
+}
+
+impl From<ImageFormat> for wgpu::TextureFormat {
+    fn from(format: ImageFormat) -> Self {
+        match format {
+            ImageFormat::Rgba8Unorm => wgpu::TextureFormat::Rgba8Unorm,
+            ImageFormat::Rgba8UnormSrgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            ImageFormat::Bgra8Unorm => wgpu::TextureFormat::Bgra8Unorm,
+            ImageFormat::Bgra8UnormSrgb => wgpu::TextureFormat::Bgra8UnormSrgb,
+            ImageFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+            ImageFormat::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
+        }
+    }
+}
+
+/// Reorder pixels read back from a `format` target into RGBA8.
+///
+/// Float targets are clamped to `0.0..=1.0` and quantized, without any tone mapping.
+pub fn to_rgba8(format: wgpu::TextureFormat, mut data: Vec<u8>) -> Result<Vec<u8>, RenderError> {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => Ok(data),
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+            for pixel in data.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+            Ok(data)
+        }
+        wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rgba32Float => {
+            Ok(to_rgba32f(format, &data)?
+                .into_iter()
+                .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+                .collect())
+        }
+        format => Err(RenderError::UnsupportedFormat(format)),
+    }
+}
+
+/// Like `to_rgba8`, but encoding the linear values of non-sRGB targets as sRGB, so every
+/// format gives the image an sRGB target would have. Alpha stays linear.
+pub fn to_srgb8(format: wgpu::TextureFormat, data: Vec<u8>) -> Result<Vec<u8>, RenderError> {
+    if format.is_srgb() {
+        return to_rgba8(format, data);
+    }
+
+    let encode = |linear: f32| {
+        let linear = linear.clamp(0.0, 1.0);
+        let encoded = if linear <= 0.0031308 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+        (encoded * 255.0).round() as u8
+    };
+
+    Ok(to_rgba32f(format, &data)?
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            [
+                encode(pixel[0]),
+                encode(pixel[1]),
+                encode(pixel[2]),
+                (pixel[3].clamp(0.0, 1.0) * 255.0).round() as u8,
+            ]
+        })
+        .collect())
+}
+
+/// Unpack pixels read back from a `format` target into RGBA32F, for HDR output.
+///
+/// 8-bit channels map to `0.0..=1.0`. sRGB targets store encoded values, they are
+/// decoded so the result is linear like the float formats.
+pub fn to_rgba32f(format: wgpu::TextureFormat, data: &[u8]) -> Result<Vec<f32>, RenderError> {
+    let unorm = |byte: u8| byte as f32 / 255.0;
+    let srgb = |byte: u8| {
+        let encoded = unorm(byte);
+        if encoded <= 0.04045 {
+            encoded / 12.92
+        } else {
+            ((encoded + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    // Alpha is always stored linearly.
+    let pixels = |decode: &dyn Fn(u8) -> f32, bgra: bool| {
+        data.chunks_exact(4)
+            .flat_map(|pixel| {
+                let (r, b) = if bgra {
+                    (pixel[2], pixel[0])
+                } else {
+                    (pixel[0], pixel[2])
+                };
+                [decode(r), decode(pixel[1]), decode(b), unorm(pixel[3])]
+            })
+            .collect()
+    };
+
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm => Ok(pixels(&unorm, false)),
+        wgpu::TextureFormat::Rgba8UnormSrgb => Ok(pixels(&srgb, false)),
+        wgpu::TextureFormat::Bgra8Unorm => Ok(pixels(&unorm, true)),
+        wgpu::TextureFormat::Bgra8UnormSrgb => Ok(pixels(&srgb, true)),
+        wgpu::TextureFormat::Rgba16Float => Ok(data
+            .chunks_exact(2)
+            .map(|half| half::f16::from_le_bytes([half[0], half[1]]).to_f32())
+            .collect()),
+        wgpu::TextureFormat::Rgba32Float => Ok(data
+            .chunks_exact(4)
+            .map(|float| f32::from_le_bytes([float[0], float[1], float[2], float[3]]))
+            .collect()),
+        format => Err(RenderError::UnsupportedFormat(format)),
+    }
+}
+
+/// Parse a clear color given as `R,G,B,A`, e.g. `0,0,0,1`.
+pub fn parse_color(arg: &str) -> Result<wgpu::Color, String> {
+    let channels = arg
+        .split(',')
+        .map(|channel| {
+            channel
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| format!("invalid color channel `{channel}`"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match channels[..] {
+        [r, g, b, a] => Ok(wgpu::Color { r, g, b, a }),
+        _ => Err(format!("expected a color as `R,G,B,A`, got `{arg}`")),
+    }
+}
+
+/// Parse a duration given in seconds, e.g. `2.5`.
+pub fn parse_seconds(arg: &str) -> Result<std::time::Duration, String> {
+    arg.parse::<f64>()
+        .ok()
+        .and_then(|seconds| std::time::Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("expected a number of seconds, got `{arg}`"))
+}
+
+/// Parse a render target size given as `WIDTHxHEIGHT`, e.g. `1920x1080`.
+pub fn parse_size(arg: &str) -> Result<(u32, u32), String> {
+    let (width, height) = arg
+        .split_once('x')
+        .ok_or_else(|| format!("expected `WIDTHxHEIGHT`, got `{arg}`"))?;
+
+    let parse = |value: &str| match value.parse::<u32>() {
+        Ok(0) => Err("the size can't be zero".to_string()),
+        Ok(value) => Ok(value),
+        Err(e) => Err(format!("invalid size `{value}`: {e}")),
+    };
+
+    Ok((parse(width)?, parse(height)?))
+}
+
+/// How the fragment shader's output is combined with what the target already holds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Blend {
+    /// Overwrite the target.
+    #[default]
+    Replace,
+    /// Mix in by the fragment's alpha.
+    Alpha,
+    /// Like `alpha`, for shaders that premultiplied their color by it.
+    PremultipliedAlpha,
+    /// Add to the target, e.g. for glows and particles.
+    Additive,
+    /// Mix in by the blend constant, `src * C + dst * (1 - C)` per channel.
+    Constant,
+}
+
+impl Blend {
+    pub fn state(self) -> Option<wgpu::BlendState> {
+        match self {
+            Blend::Replace => None,
+            Blend::Alpha => Some(wgpu::BlendState::ALPHA_BLENDING),
+            Blend::PremultipliedAlpha => Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            Blend::Additive => {
+                let add = wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                };
+                Some(wgpu::BlendState {
+                    color: add,
+                    alpha: add,
+                })
+            }
+            Blend::Constant => {
+                let mix = wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Constant,
+                    dst_factor: wgpu::BlendFactor::OneMinusConstant,
+                    operation: wgpu::BlendOperation::Add,
+                };
+                Some(wgpu::BlendState {
+                    color: mix,
+                    alpha: mix,
+                })
+            }
+        }
+    }
+}
+
+/// Vertex input location of the layer being drawn in renders of several layers.
+///
+/// The layer index goes through a vertex buffer rather than `@builtin(instance_index)`,
+/// which doesn't include the first instance on GL.
+pub const LAYER_LOCATION: u32 = 8;
+
+/// Default background of the offscreen renders, where the quad doesn't cover the target.
+pub const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+
+/// Settings for a single offscreen render.
+pub struct RenderOptions<'a> {
+    /// Render target size in pixels.
+    pub size: (u32, u32),
+    pub format: wgpu::TextureFormat,
+    /// Contents of a uniform buffer bound at `@group(0) @binding(0)`.
+    pub uniforms: Option<&'a [u8]>,
+    /// Samples per pixel, the target is resolved before it is read back. Counts the
+    /// format doesn't support here fall back to 1.
+    pub sample_count: u32,
+    /// Color the target is cleared to before drawing, `None` keeps its contents, which for
+    /// a freshly created target are transparent black.
+    pub clear_color: Option<wgpu::Color>,
+    /// Blending applied to the color target.
+    pub blend: Option<wgpu::BlendState>,
+    /// Color used by `BlendFactor::Constant` and `BlendFactor::OneMinusConstant`.
+    pub blend_constant: Option<wgpu::Color>,
+    /// Row layout of the staging buffer the target is copied into.
+    pub readback: ReadbackAlignment,
+    /// Only draw where this mask is set, using a stencil attachment filled by a first draw.
+    pub stencil_mask: Option<&'a StencilMask>,
+    /// Test fragments against a depth attachment cleared to 1.0 with this function, and
+    /// write their depth. Without it, there is no depth attachment.
+    pub depth_test: Option<wgpu::CompareFunction>,
+    /// Push constant data for the vertex and fragment stages, starting at offset 0.
+    pub push_constants: Option<&'a [u8]>,
+    /// Texture bound at `@group(0) @binding(1)`, with a linear sampler at `@binding(2)`.
+    pub input_texture: Option<&'a InputTexture>,
+    /// Entry points drawing the quad.
+    pub vertex_entry: &'a str,
+    pub fragment_entry: &'a str,
+    /// Module holding `vertex_entry`, for shaders that only have a fragment stage like
+    /// GLSL ones. The shader itself is used if not given.
+    pub vertex_shader: Option<&'a naga::Module>,
+    /// Time the main render pass with timestamp queries, see `PendingReadback::take_gpu_timer`.
+    pub profile: bool,
+    /// Draw without a fragment stage, for vertex-only shaders like depth prepasses, which
+    /// is also done for shaders that have no fragment entry point. It needs `depth_test`,
+    /// and the depth is drawn into the target as gray to be read back.
+    pub depth_only: bool,
+    /// Give up on the readback of `render` and `render_mesh` after this long instead of
+    /// blocking until the GPU is done, which may be never for a shader that hangs it.
+    pub timeout: Option<std::time::Duration>,
+    /// Array layers of the target, read back one after the other. With more than one, each
+    /// is drawn with its index as a `u32` vertex input at `LAYER_LOCATION`. Six square
+    /// layers are the faces of a cubemap, in +X, -X, +Y, -Y, +Z, -Z order.
+    pub layers: u32,
+    /// Color targets drawn at once, up to 8, one for every `@location` the fragment shader
+    /// writes. All have `format` and `blend`, and are read back one after the other, each
+    /// with all its layers.
+    pub color_targets: u32,
+}
+
+impl Default for RenderOptions<'_> {
+    fn default() -> Self {
+        Self {
+            size: (128, 128),
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            uniforms: None,
+            sample_count: 1,
+            clear_color: Some(CLEAR_COLOR),
+            blend: None,
+            blend_constant: None,
+            readback: ReadbackAlignment::Auto,
+            stencil_mask: None,
+            depth_test: None,
+            push_constants: None,
+            input_texture: None,
+            vertex_entry: "vs_main",
+            fragment_entry: "fs_main",
+            vertex_shader: None,
+            profile: false,
+            depth_only: false,
+            timeout: None,
+            layers: 1,
+            color_targets: 1,
+        }
+    }
+}
+
+/// Draw a fullscreen quad with the shader's `options.vertex_entry` and `options.fragment_entry`,
+/// `vs_main` and `fs_main` by default, and read the target back.
+///
+/// The returned pixels are tightly packed, whatever row layout `options.readback` picked.
+pub fn render(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    options: &RenderOptions,
+) -> Result<Vec<u8>, RenderError> {
+    render_deferred(gpu, shader_source, options)?.wait_for(&gpu.device, options.timeout)
+}
+
+/// Render at `size` into a `format` target and return the pixels as tightly packed RGBA8,
+/// exactly `4 * width * height` bytes.
+fn render_rgba8(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    size: (u32, u32),
+    format: wgpu::TextureFormat,
+) -> Result<Vec<u8>, RenderError> {
+    let options = RenderOptions {
+        size,
+        format,
+        ..Default::default()
+    };
+    let data = to_rgba8(format, render(gpu, shader_source, &options)?)?;
+    assert_eq!(
+        data.len(),
+        4 * size.0 as usize * size.1 as usize,
+        "readback returns tightly packed rows of the target size"
+    );
+
+    Ok(data)
+}
+
+/// Render at `size` and return the pixels as tightly packed RGBA8 bytes, row after row
+/// from the top, without touching the filesystem.
+pub fn render_to_rgba(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    size: (u32, u32),
+) -> Result<Vec<u8>, RenderError> {
+    render_rgba8(gpu, shader_source, size, RenderOptions::default().format)
+}
+
+/// Render at `size` into a `format` target and return the result as an RGBA8 image.
+pub fn render_to_image(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    size: (u32, u32),
+    format: wgpu::TextureFormat,
+) -> Result<image::RgbaImage, RenderError> {
+    let data = render_rgba8(gpu, shader_source, size, format)?;
+
+    Ok(image::RgbaImage::from_raw(size.0, size.1, data).expect("the size was checked"))
+}
+
+/// Render with the image at `input_path` bound as a 2D texture and sampler, and save the
+/// result as a PNG at `output_path`.
+pub fn render_with_input(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    input_path: &std::path::Path,
+    color_space: crate::texture::ColorSpace,
+    output_path: &std::path::Path,
+) -> Result<(), String> {
+    let input = InputTexture::load(
+        crate::texture::Dimension::D2,
+        color_space,
+        Default::default(),
+        &[input_path.into()],
+    )?;
+    let options = RenderOptions {
+        input_texture: Some(&input),
+        ..Default::default()
+    };
+    let data = render(gpu, shader_source, &options).map_err(|e| e.to_string())?;
+
+    let metadata = crate::output::ImageMetadata::new(&input_path.to_string_lossy(), &[]);
+    crate::output::save_png_with_metadata(
+        output_path,
+        options.size.0,
+        options.size.1,
+        &data,
+        &metadata,
+    )
+    .map_err(|e| format!("failed to save {}: {e}", output_path.display()))
+}
+
+/// Like `render`, but returns once the work is submitted instead of blocking on the readback.
+pub fn render_deferred(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    options: &RenderOptions,
+) -> Result<PendingReadback, RenderError> {
+    render_mesh_deferred(gpu, shader_source, options, &Vertex2d::quad())
+}
+
+/// Like `render_deferred`, drawing `geometry`.
+pub fn render_geometry_deferred(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    options: &RenderOptions,
+    geometry: Geometry,
+) -> Result<PendingReadback, RenderError> {
+    match geometry {
+        Geometry::Quad => render_deferred(gpu, shader_source, options),
+        Geometry::Quad3d => render_mesh_deferred(gpu, shader_source, options, &Vertex3d::quad()),
+        Geometry::Cube => render_mesh_deferred(gpu, shader_source, options, &Vertex3d::cube()),
+        Geometry::Fullscreen => render_fullscreen_deferred(gpu, shader_source, options),
+    }
+}
+
+/// Draw a triangle covering the target with `draw(0..3)`, without vertex or index buffers,
+/// and read the target back. The shader's vertex stage makes the positions from
+/// `@builtin(vertex_index)`, the way `fullscreen.wgsl` does.
+///
+/// Only `size`, `format`, `uniforms`, `clear_color`, `blend`, `readback`, `timeout` and the
+/// entry points of the options apply.
+pub fn render_fullscreen(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    options: &RenderOptions,
+) -> Result<Vec<u8>, RenderError> {
+    render_fullscreen_deferred(gpu, shader_source, options)?.wait_for(&gpu.device, options.timeout)
+}
+
+/// Like `render_fullscreen`, but returns once the work is submitted.
+pub fn render_fullscreen_deferred(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    options: &RenderOptions,
+) -> Result<PendingReadback, RenderError> {
+    let device = &gpu.device;
+
+    if let wgpu::ShaderSource::Naga(module) = &shader_source {
+        check_entry_point(module, naga::ShaderStage::Vertex, options.vertex_entry)?;
+        check_entry_point(module, naga::ShaderStage::Fragment, options.fragment_entry)?;
+    }
+    if options.blend_constant.is_none() && options.blend.is_some_and(uses_blend_constant) {
+        return Err(RenderError::MissingBlendConstant);
+    }
+
+    let format = options.format;
+    let pixel_size = format
+        .block_size(None)
+        .ok_or(RenderError::UnsupportedFormat(format))?;
+    options
+        .readback
+        .bytes_per_row(pixel_size * options.size.0)?;
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("fullscreen texture"),
+        size: Extent3d {
+            width: options.size.0,
+            height: options.size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let binding = options.uniforms.map(|contents| {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("uniform buffer"),
+            contents,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("group 0 bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("group 0 bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        (layout, bind_group)
+    });
+    let bind_group_layouts: Vec<&wgpu::BindGroupLayout> =
+        binding.iter().map(|(layout, _)| layout).collect();
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = capture_validation(device, || {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: shader_source,
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("fullscreen pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: options.vertex_entry,
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: options.fragment_entry,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: options.blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    })?;
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("fullscreen render encoder"),
+    });
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("fullscreen render pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: options
+                        .clear_color
+                        .map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&pipeline);
+        if let Some(color) = options.blend_constant {
+            render_pass.set_blend_constant(color);
+        }
+        if let Some((_, bind_group)) = &binding {
+            render_pass.set_bind_group(0, bind_group, &[]);
+        }
+        render_pass.draw(0..3, 0..1);
+    }
+
+    submit_read_back(gpu, encoder, &texture, options.readback)
+}
+
+/// Draw `mesh` instead of the fullscreen quad, with a pipeline taking `V` vertices.
+pub fn render_mesh<V: VertexBuffer>(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    options: &RenderOptions,
+    mesh: &Mesh<V>,
+) -> Result<Vec<u8>, RenderError> {
+    render_mesh_deferred(gpu, shader_source, options, mesh)?.wait_for(&gpu.device, options.timeout)
+}
+
+/// Like `render_mesh`, but returns once the work is submitted.
+pub fn render_mesh_deferred<V: VertexBuffer>(
+    gpu: &Gpu,
+    shader_source: wgpu::ShaderSource,
+    options: &RenderOptions,
+    mesh: &Mesh<V>,
+) -> Result<PendingReadback, RenderError> {
+    let device = &gpu.device;
+    let queue = &gpu.queue;
+
+    // wgpu panics on a missing entry point, and only WGSL source would need parsing first.
+    if let Some(module) = options.vertex_shader {
+        check_entry_point(module, naga::ShaderStage::Vertex, options.vertex_entry)?;
+    }
+    let mut depth_only = options.depth_only;
+    if let wgpu::ShaderSource::Naga(module) = &shader_source {
+        if options.vertex_shader.is_none() {
+            check_entry_point(module, naga::ShaderStage::Vertex, options.vertex_entry)?;
+        }
+        depth_only |= !module
+            .entry_points
+            .iter()
+            .any(|entry_point| entry_point.stage == naga::ShaderStage::Fragment);
+        if !depth_only {
+            check_entry_point(module, naga::ShaderStage::Fragment, options.fragment_entry)?;
+
+            let outputs = module
+                .entry_points
+                .iter()
+                .find(|entry_point| entry_point.name == options.fragment_entry)
+                .map_or(0, |entry_point| reflect::color_outputs(module, entry_point));
+            // wgpu lets a shader that writes no color at all draw into any targets.
+            if outputs > 0 && outputs != options.color_targets {
+                return Err(RenderError::ColorTargetMismatch {
+                    targets: options.color_targets,
+                    outputs,
+                });
+            }
+        }
+    }
+    if depth_only {
+        if options.color_targets > 1 {
+            return Err(RenderError::ColorTargetMismatch {
+                targets: options.color_targets,
+                outputs: 0,
+            });
+        }
+        if options.depth_test.is_none() {
+            return Err(RenderError::DepthOnlyWithoutDepthTest);
+        }
+        if options.stencil_mask.is_some() {
+            return Err(RenderError::DepthOnlyWithStencilMask);
+        }
+    }
+    let layer_limit = device.limits().max_texture_array_layers;
+    if !(1..=layer_limit).contains(&options.layers) {
+        return Err(RenderError::InvalidLayers {
+            layers: options.layers,
+            limit: layer_limit,
+        });
+    }
+    if options.profile && !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+        return Err(RenderError::TimestampsUnsupported);
+    }
+
+    let shader_desc = wgpu::ShaderModuleDescriptor {
+        label: None,
+        source: shader_source,
+    };
+
+    let (shader, vertex_shader) = capture_validation(device, || {
+        let shader = device.create_shader_module(shader_desc);
+        let vertex_shader = options.vertex_shader.map(|module| {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("vertex shader"),
+                source: wgpu::ShaderSource::Naga(std::borrow::Cow::Owned(module.clone())),
+            })
+        });
+        (shader, vertex_shader)
+    })?;
+
+    let texture_size = options.size;
+    let format = options.format;
+    let pixel_size = format
+        .block_size(None)
+        .ok_or(RenderError::UnsupportedFormat(format))?;
+    // Fail before any GPU work if the rows can't be read back the way that was asked for.
+    options
+        .readback
+        .bytes_per_row(pixel_size * texture_size.0)?;
+
+    // Every color target is a run of `layers` layers of the same texture, so they are all
+    // read back by one copy.
+    let layers = options.color_targets * options.layers;
+    // The GL backend makes square textures of six layers, or a multiple, into cubemaps,
+    // which it can't copy to a buffer, so those get a spare layer there.
+    let cube_compatible = texture_size.0 == texture_size.1 && layers.is_multiple_of(6);
+    let allocated_layers = if cube_compatible && gpu.adapter.get_info().backend == wgpu::Backend::Gl
+    {
+        layers + 1
+    } else {
+        layers
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: Extent3d {
+            width: texture_size.0,
+            height: texture_size.1,
+            depth_or_array_layers: allocated_layers,
+        },
+        mip_level_count: 1,
+        // Multisampled textures can't be copied, this is the resolve target then.
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+
+    // Every layer is drawn by a pass of its own. Multiview would draw them all at once,
+    // but it needs a feature few devices have and isn't a fit for cubemaps.
+    let layer_view = |texture: &wgpu::Texture, layer| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })
+    };
+    // The views of every color target, by layer.
+    let layer_views: Vec<Vec<wgpu::TextureView>> = (0..options.layers)
+        .map(|layer| {
+            (0..options.color_targets)
+                .map(|target| layer_view(&texture, target * options.layers + layer))
+                .collect()
+        })
+        .collect();
+
+    let sample_count = if depth_only && options.sample_count > 1 {
+        log::warn!("Renders without a fragment stage aren't multisampled, rendering with 1 sample");
+        1
+    } else if options.sample_count == 1 || gpu.supports_sample_count(format, options.sample_count) {
+        options.sample_count
+    } else {
+        log::warn!(
+            "{format:?} targets can't have {} samples on this device, rendering with 1",
+            options.sample_count
+        );
+        1
+    };
+    let options = &RenderOptions {
+        sample_count,
+        depth_only,
+        ..*options
+    };
+
+    // Multisampled textures can't have layers, every color target gets its own.
+    let multisampled_textures: Option<Vec<wgpu::Texture>> = (sample_count > 1).then(|| {
+        (0..options.color_targets)
+            .map(|_| {
+                device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("multisampled texture"),
+                    size: Extent3d {
+                        width: texture_size.0,
+                        height: texture_size.1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                })
+            })
+            .collect()
+    });
+    let multisampled_views: Option<Vec<wgpu::TextureView>> =
+        multisampled_textures.as_ref().map(|textures| {
+            textures
+                .iter()
+                .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+                .collect()
+        });
+
+    if options.blend_constant.is_none() && options.blend.is_some_and(uses_blend_constant) {
+        return Err(RenderError::MissingBlendConstant);
+    }
+
+    // wgpu only reports this as a validation error on the pipeline layout, not naming the sizes.
+    let push_constant_size = options.push_constants.map_or(0, |data| data.len() as u32);
+    let push_constant_limit = device.limits().max_push_constant_size;
+    if push_constant_size > push_constant_limit {
+        return Err(RenderError::PushConstantsTooLarge {
+            size: push_constant_size,
+            limit: push_constant_limit,
+        });
+    }
+
+    // Uniforms and the input texture share group 0, shaders using neither get no bind group.
+    let uniform_buffer = options.uniforms.map(|contents| {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("uniform buffer"),
+            contents,
+            usage: wgpu::BufferUsages::UNIFORM,
+        })
+    });
+    let input_texture = options
+        .input_texture
+        .map(|input| (input.upload(device, queue), input.view_dimension()));
+
+    let mut layout_entries = Vec::new();
+    let mut entries = Vec::new();
+
+    if let Some(buffer) = &uniform_buffer {
+        layout_entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        });
+    }
+
+    if let Some(((view, sampler), view_dimension)) = &input_texture {
+        layout_entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: *view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        });
+        layout_entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(view),
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::Sampler(sampler),
+        });
+    }
+
+    let binding = (!entries.is_empty()).then(|| {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("group 0 bind group layout"),
+            entries: &layout_entries,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("group 0 bind group"),
+            layout: &layout,
+            entries: &entries,
+        });
+
+        (layout, bind_group)
+    });
+
+    let bind_group_layouts: Vec<&wgpu::BindGroupLayout> =
+        binding.iter().map(|(layout, _)| layout).collect();
+
+    let push_constant_ranges: Vec<wgpu::PushConstantRange> = options
+        .push_constants
+        .map(|_| wgpu::PushConstantRange {
+            stages: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            range: 0..push_constant_size,
+        })
+        .into_iter()
+        .collect();
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &bind_group_layouts,
+        push_constant_ranges: &push_constant_ranges,
+    });
+
+    let pipeline = capture_validation(device, || {
+        mesh_pipeline::<V>(
+            device,
+            vertex_shader.as_ref().unwrap_or(&shader),
+            &shader,
+            &pipeline_layout,
+            format,
+            options,
+        )
+    })?;
+
+    let (vertex_buffer, index_buffer) = mesh.buffers(device);
+    let layer_buffer = (options.layers > 1).then(|| {
+        let layers: Vec<u32> = (0..options.layers).collect();
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("layer buffer"),
+            contents: bytemuck::cast_slice(&layers),
+            usage: wgpu::BufferUsages::VERTEX,
+        })
+    });
+
+    let mask_pass = options.stencil_mask.map(|mask| {
+        MaskPass::new(
+            device,
+            queue,
+            mask,
+            format,
+            sample_count,
+            options.color_targets,
+        )
+    });
+
+    let depth_stencil_format = depth_stencil_format(options);
+    let depth_stencil_texture = depth_stencil_format.map(|depth_stencil_format| {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("depth stencil texture"),
+            size: Extent3d {
+                width: texture_size.0,
+                height: texture_size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: depth_stencil_format,
+            // Without a fragment stage, the depth is drawn into the target afterwards.
+            usage: if depth_only {
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            } else {
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+            },
+            view_formats: &[],
+        })
+    });
+    let depth_stencil_view = depth_stencil_texture
+        .as_ref()
+        .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+
+    // A pipeline whose sample count differs from an attachment's makes wgpu fail deep
+    // inside the render pass, so catch it here with a readable error.
+    let mut attachments = Vec::new();
+    match &multisampled_textures {
+        // Drawn into, then resolved into the target.
+        Some(textures) => attachments.extend(
+            textures
+                .iter()
+                .map(|texture| ("multisampled color", texture.sample_count())),
+        ),
+        None if !depth_only => attachments.push(("color", texture.sample_count())),
+        None => {}
+    }
+    attachments.extend(
+        depth_stencil_texture
+            .iter()
+            .map(|texture| ("depth/stencil", texture.sample_count())),
+    );
+    attachments.extend(
+        mask_pass
+            .iter()
+            .map(|mask_pass| ("stencil mask pass", mask_pass.sample_count())),
+    );
+    check_sample_counts(options.sample_count, &attachments)?;
+
+    // Written at the beginning and the end of the main render pass.
+    let timestamps = options.profile.then(|| {
+        device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("render pass timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        })
+    });
+
+    let load = options
+        .clear_color
+        .map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("main render encoder"),
+    });
+
+    let last_layer = options.layers - 1;
+    // The layer is drawn as the instance of that index, for `LAYER_LOCATION` to hold it.
+    for (layer, target_views) in (0..).zip(&layer_views) {
+        // These are what @location(0), @location(1) and so on in the fragment shader target.
+        // With MSAA, draw into the multisampled texture and resolve into the target.
+        let color_attachments: Vec<_> = target_views
+            .iter()
+            .enumerate()
+            .map(|(target, texture_view)| {
+                Some(match &multisampled_views {
+                    Some(views) => wgpu::RenderPassColorAttachment {
+                        view: &views[target],
+                        resolve_target: Some(texture_view),
+                        ops: wgpu::Operations {
+                            load,
+                            store: StoreOp::Discard,
+                        },
+                    },
+                    None => wgpu::RenderPassColorAttachment {
+                        view: texture_view, // Change this to change where to draw.
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load,
+                            store: StoreOp::Store,
+                        },
+                    },
+                })
+            })
+            // Without a fragment stage, there are no color targets at all.
+            .filter(|_| !depth_only)
+            .collect();
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("main render pass"),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment: depth_stencil_view.as_ref().map(|view| {
+                    wgpu::RenderPassDepthStencilAttachment {
+                        view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: if depth_only {
+                                StoreOp::Store
+                            } else {
+                                StoreOp::Discard
+                            },
+                        }),
+                        // A depth-only format has no stencil aspect to clear.
+                        stencil_ops: mask_pass.as_ref().map(|_| wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(0),
+                            store: StoreOp::Discard,
+                        }),
+                    }
+                }),
+                // The timestamps span the passes of all layers.
+                timestamp_writes: timestamps
+                    .as_ref()
+                    .filter(|_| layer == 0 || layer == last_layer)
+                    .map(|query_set| wgpu::RenderPassTimestampWrites {
+                        query_set,
+                        beginning_of_pass_write_index: (layer == 0).then_some(0),
+                        end_of_pass_write_index: (layer == last_layer).then_some(1),
+                    }),
+                occlusion_query_set: None,
+            });
+
+            // Fill the stencil attachment before the main draw tests against it.
+            if let Some(mask_pass) = &mask_pass {
+                mask_pass.draw(&mut render_pass);
+            }
+
+            // Set vertex buffer for VertexInput.
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            if let Some(layer_buffer) = &layer_buffer {
+                render_pass.set_vertex_buffer(1, layer_buffer.slice(..));
+            }
+
+            render_pass.set_index_buffer(index_buffer.slice(..), mesh.indices.format());
+
+            render_pass.set_pipeline(&pipeline);
+
+            if let Some(color) = options.blend_constant {
+                render_pass.set_blend_constant(color);
+            }
+
+            if let Some((_, bind_group)) = &binding {
+                render_pass.set_bind_group(0, bind_group, &[]);
+            }
+
+            if let Some(data) = options.push_constants {
+                render_pass.set_push_constants(wgpu::ShaderStages::VERTEX_FRAGMENT, 0, data);
+            }
+
+            render_pass.draw_indexed(0..mesh.index_count(), 0, layer..layer + 1);
+        }
+
+        if let (true, Some(depth_view)) = (depth_only, &depth_stencil_view) {
+            draw_depth(device, &mut encoder, depth_view, &target_views[0], format);
+        }
+    }
+
+    let Some(query_set) = &timestamps else {
+        return submit_layers_read_back(gpu, encoder, &texture, layers, options.readback);
+    };
+
+    // Query sets can only be resolved into buffers that can't be mapped, so this is copied
+    // to a staging buffer of its own along with the pixels.
+    let timestamp_size = 2 * std::mem::size_of::<u64>() as BufferAddress;
+    let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("timestamp resolve buffer"),
+        usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+        size: timestamp_size,
+        mapped_at_creation: false,
+    });
+    let timestamp_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("timestamp staging buffer"),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        size: timestamp_size,
+        mapped_at_creation: false,
+    });
+    encoder.resolve_query_set(query_set, 0..2, &resolve_buffer, 0);
+    encoder.copy_buffer_to_buffer(&resolve_buffer, 0, &timestamp_buffer, 0, timestamp_size);
+
+    let mut pending = submit_layers_read_back(gpu, encoder, &texture, layers, options.readback)?;
+    let size = timestamp_size as u32;
+    pending.gpu_timer = Some(Box::new(GpuTimer {
+        readback: PendingReadback::map(timestamp_buffer, size, size, false),
+        period: queue.get_timestamp_period(),
+    }));
+
+    Ok(pending)
+}
+
+/// Draw a `Depth32Float` attachment into a `format` target as opaque gray, the near plane
+/// black and the far plane white.
+///
+/// Depth can't be copied to a buffer on every backend, so it is read back through a color
+/// target like any other render.
+fn draw_depth(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    depth_view: &wgpu::TextureView,
+    target_view: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+) {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("depth view shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("depth_view.wgsl").into()),
+    });
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("depth view bind group layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        }],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("depth view bind group"),
+        layout: &bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(depth_view),
+        }],
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let options = RenderOptions {
+        format,
+        ..Default::default()
+    };
+    let pipeline = quad_pipeline(device, &shader, &layout, format, &options);
+    let (vertex_buffer, index_buffer) = quad_buffers(device);
+
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("depth view pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    render_pass.set_pipeline(&pipeline);
+    render_pass.set_bind_group(0, &bind_group, &[]);
+    render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+    render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+    render_pass.draw_indexed(0..6, 0, 0..1);
+}
+
+/// The timestamps of a profiled render, being read back along with its pixels.
+pub struct GpuTimer {
+    readback: PendingReadback,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+}
+
+impl GpuTimer {
+    /// Block until the timestamps are read back and return the time between them.
+    pub fn wait(self, device: &wgpu::Device) -> Result<std::time::Duration, RenderError> {
+        let period = self.period;
+        let data = self.readback.wait(device)?;
+        let timestamps: &[u64] = bytemuck::cast_slice(&data);
+        let ticks = timestamps[1].saturating_sub(timestamps[0]);
+
+        Ok(std::time::Duration::from_nanos(
+            (ticks as f64 * period as f64) as u64,
+        ))
+    }
+}
+
+/// Fail with the entry points `module` does have for `stage` if none is called `name`.
+pub(crate) fn check_entry_point(
+    module: &naga::Module,
+    stage: naga::ShaderStage,
+    name: &str,
+) -> Result<(), RenderError> {
+    let available: Vec<String> = module
+        .entry_points
+        .iter()
+        .filter(|entry_point| entry_point.stage == stage)
+        .map(|entry_point| entry_point.name.clone())
+        .collect();
+
+    if available.iter().any(|available| available == name) {
+        return Ok(());
+    }
+
+    Err(RenderError::MissingEntryPoint {
+        stage,
+        name: name.into(),
+        available,
+    })
+}
+
+/// Format of the depth attachment of renders with a depth test but no stencil mask.
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Format of the depth stencil attachment `options` need, if any. The stencil mask's
+/// format has a depth aspect too, so it serves both.
+fn depth_stencil_format(options: &RenderOptions) -> Option<wgpu::TextureFormat> {
+    match (options.stencil_mask, options.depth_test) {
+        (Some(_), _) => Some(stencil::STENCIL_FORMAT),
+        (None, Some(_)) => Some(DEPTH_FORMAT),
+        (None, None) => None,
+    }
+}
+
+/// The fullscreen quad pipeline drawing the entry points of `options` into a `format` target,
+/// with the blending, stencil test and sample count of `options`.
+pub fn quad_pipeline(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    options: &RenderOptions,
+) -> wgpu::RenderPipeline {
+    mesh_pipeline::<Vertex2d>(device, shader, shader, layout, format, options)
+}
+
+/// Like `quad_pipeline`, for meshes of `V` vertices, with the vertex stage from its own module.
+pub(crate) fn mesh_pipeline<V: VertexBuffer>(
+    device: &wgpu::Device,
+    vertex_shader: &wgpu::ShaderModule,
+    fragment_shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    options: &RenderOptions,
+) -> wgpu::RenderPipeline {
+    let layer_attributes = wgpu::vertex_attr_array![LAYER_LOCATION => Uint32];
+    let buffers = [
+        V::desc(),
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &layer_attributes,
+        },
+    ];
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: vertex_shader,
+            entry_point: options.vertex_entry,
+            buffers: &buffers[..if options.layers > 1 { 2 } else { 1 }],
+        },
+        fragment: (!options.depth_only).then_some(wgpu::FragmentState {
+            module: fragment_shader,
+            entry_point: options.fragment_entry,
+            targets: &vec![
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: options.blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                });
+                options.color_targets as usize
+            ],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+            polygon_mode: wgpu::PolygonMode::Fill,
+            // Requires Features::DEPTH_CLIP_CONTROL
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: match (options.stencil_mask, options.depth_test) {
+            (Some(_), depth_test) => Some(stencil::masked_depth_stencil(depth_test)),
+            (None, Some(compare)) => Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            (None, None) => None,
+        },
+        multisample: wgpu::MultisampleState {
+            count: options.sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        // If the pipeline will be used with a multiview resource pass, this
+        // indicates how many array layers the attachments will have.
+        multiview: None,
+    })
+}
+
+/// Vertex and index buffers of the fullscreen quad drawn with `draw_indexed(0..6, 0, 0..1)`.
+pub fn quad_buffers(device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
+    Vertex2d::quad().buffers(device)
+}
+
+/// Copy an RGBA8 texture into a staging buffer as the last command of `encoder`, submit it
+/// and block until the tightly packed pixels are available.
+pub(crate) fn read_back(
+    gpu: &Gpu,
+    encoder: wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    readback: ReadbackAlignment,
+) -> Result<Vec<u8>, RenderError> {
+    submit_read_back(gpu, encoder, texture, readback)?.wait(&gpu.device)
+}
+
+/// Like `read_back`, but returns as soon as the work is submitted.
+pub(crate) fn submit_read_back(
+    gpu: &Gpu,
+    encoder: wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    readback: ReadbackAlignment,
+) -> Result<PendingReadback, RenderError> {
+    let layers = texture.depth_or_array_layers();
+    submit_layers_read_back(gpu, encoder, texture, layers, readback)
+}
+
+/// Like `submit_read_back`, copying only the first `layers` layers of the texture.
+fn submit_layers_read_back(
+    gpu: &Gpu,
+    mut encoder: wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    layers: u32,
+    readback: ReadbackAlignment,
+) -> Result<PendingReadback, RenderError> {
+    let device = &gpu.device;
+    let queue = &gpu.queue;
+
+    let texture_size = (texture.width(), texture.height());
+    let pixel_size = texture
+        .format()
+        .block_size(None)
+        .ok_or(RenderError::UnsupportedFormat(texture.format()))?;
+    let (bytes_per_row, padded) = readback.bytes_per_row(pixel_size * texture_size.0)?;
+
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("staging buffer"),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        size: (bytes_per_row * texture_size.1) as BufferAddress * layers as BufferAddress,
+        mapped_at_creation: false,
+    });
+
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            aspect: wgpu::TextureAspect::All,
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &staging_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(texture_size.1),
+            },
+        },
+        wgpu::Extent3d {
+            width: texture_size.0,
+            height: texture_size.1,
+            depth_or_array_layers: layers,
+        },
+    );
+
+    queue.submit(Some(encoder.finish()));
+
+    Ok(PendingReadback::map(
+        staging_buffer,
+        pixel_size * texture_size.0,
+        bytes_per_row,
+        padded,
+    ))
+}
+
+/// Copy a whole buffer into a staging buffer as the last command of `encoder` and submit
+/// it, for reading back the results of a compute pass.
+///
+/// The buffer needs `COPY_SRC` usage.
+pub(crate) fn submit_buffer_read_back(
+    gpu: &Gpu,
+    mut encoder: wgpu::CommandEncoder,
+    buffer: &wgpu::Buffer,
+) -> PendingReadback {
+    let staging_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("staging buffer"),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        size: buffer.size(),
+        mapped_at_creation: false,
+    });
+
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+    gpu.queue.submit(Some(encoder.finish()));
+
+    let size = buffer.size() as u32;
+    PendingReadback::map(staging_buffer, size, size, false)
+}
+
+/// Pixels of a submitted render that are still being copied to a mappable buffer.
+///
+/// `wait` blocks the thread like the standalone binary always has. Applications that run
+/// their own event loop can call `try_take` once per iteration instead, or await `receive`.
+pub struct PendingReadback {
+    staging_buffer: wgpu::Buffer,
+    mapped: std::sync::Arc<std::sync::Mutex<MapState>>,
+    unpadded_row: u32,
+    bytes_per_row: u32,
+    padded: bool,
+    gpu_timer: Option<Box<GpuTimer>>,
+}
+
+/// What the `map_async` callback of a `PendingReadback` hands over.
+#[derive(Default)]
+struct MapState {
+    result: Option<Result<(), wgpu::BufferAsyncError>>,
+    /// The task awaiting `receive`, woken by the callback.
+    waker: Option<std::task::Waker>,
+}
+
+impl PendingReadback {
+    /// Start mapping a staging buffer whose copy has been submitted.
+    fn map(
+        staging_buffer: wgpu::Buffer,
+        unpadded_row: u32,
+        bytes_per_row: u32,
+        padded: bool,
+    ) -> Self {
+        let mapped = std::sync::Arc::new(std::sync::Mutex::new(MapState::default()));
+        let sender = mapped.clone();
+        staging_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let waker = {
+                    let mut state = sender.lock().unwrap();
+                    state.result = Some(result);
+                    state.waker.take()
+                };
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            });
+
+        Self {
+            staging_buffer,
+            mapped,
+            unpadded_row,
+            bytes_per_row,
+            padded,
+            gpu_timer: None,
+        }
+    }
+
+    /// Take the timer of a render made with `RenderOptions::profile`, `None` otherwise.
+    pub fn take_gpu_timer(&mut self) -> Option<GpuTimer> {
+        self.gpu_timer.take().map(|timer| *timer)
+    }
+
+    /// Block until the copy has finished.
+    pub fn wait(self, device: &wgpu::Device) -> Result<Vec<u8>, RenderError> {
+        device.poll(wgpu::Maintain::Wait);
+        self.try_take(device)
+            .expect("buffer mapping finishes within Maintain::Wait")
+    }
+
+    /// Poll the device until the copy has finished, failing with `RenderError::Timeout` once
+    /// `timeout` has passed.
+    pub fn wait_timeout(
+        self,
+        device: &wgpu::Device,
+        timeout: std::time::Duration,
+    ) -> Result<Vec<u8>, RenderError> {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            if let Some(result) = self.try_take(device) {
+                return result;
+            }
+            if std::time::Instant::now() >= deadline {
+                return Err(RenderError::Timeout(timeout));
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    /// `wait_timeout` with a timeout, `wait` without.
+    pub fn wait_for(
+        self,
+        device: &wgpu::Device,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Vec<u8>, RenderError> {
+        match timeout {
+            Some(timeout) => self.wait_timeout(device, timeout),
+            None => self.wait(device),
+        }
+    }
+
+    /// Poll the device without blocking, returning the pixels once they are available.
+    ///
+    /// After this has returned `Some`, it must not be called again.
+    pub fn try_take(&self, device: &wgpu::Device) -> Option<Result<Vec<u8>, RenderError>> {
+        device.poll(wgpu::Maintain::Poll);
+
+        let result = self.mapped.lock().unwrap().result.take()?;
+        Some(result.map_err(RenderError::Map).map(|()| self.pixels()))
+    }
+
+    /// Wait for the copy without blocking the thread, woken by the mapping callback.
+    ///
+    /// wgpu only runs the callback while the device is polled, which every poll of this
+    /// future does once, without blocking. Something else has to poll it in between, e.g.
+    /// the application's event loop or a thread calling `Device::poll(Maintain::Wait)`,
+    /// or this never finishes.
+    pub async fn receive(self, device: &wgpu::Device) -> Result<Vec<u8>, RenderError> {
+        let result = std::future::poll_fn(|cx| {
+            device.poll(wgpu::Maintain::Poll);
+
+            // Checked and registered under one lock, so a callback in between isn't missed.
+            let mut state = self.mapped.lock().unwrap();
+            match state.result.take() {
+                Some(result) => std::task::Poll::Ready(result),
+                None => {
+                    state.waker = Some(cx.waker().clone());
+                    std::task::Poll::Pending
+                }
+            }
+        })
+        .await;
+
+        result.map_err(RenderError::Map).map(|()| self.pixels())
+    }
+
+    fn pixels(&self) -> Vec<u8> {
+        let data = {
+            let mapped = self.staging_buffer.slice(..).get_mapped_range();
+
+            if self.padded {
+                strip_row_padding(&mapped, self.unpadded_row, self.bytes_per_row)
+            } else {
+                mapped.to_vec()
+            }
+        };
+
+        self.staging_buffer.unmap();
+
+        data
+    }
+}
+
+/// Copy the visible part of every row out of a buffer whose rows are `padded_row` bytes apart.
+fn strip_row_padding(data: &[u8], unpadded_row: u32, padded_row: u32) -> Vec<u8> {
+    data.chunks_exact(padded_row as usize)
+        .flat_map(|row| &row[..unpadded_row as usize])
+        .copied()
+        .collect()
+}
+
+/// Check that every `(name, sample count)` attachment has the pipeline's sample count.
+fn check_sample_counts(
+    pipeline: u32,
+    attachments: &[(&'static str, u32)],
+) -> Result<(), RenderError> {
+    match attachments.iter().find(|(_, target)| *target != pipeline) {
+        Some(&(attachment, target)) => Err(RenderError::SampleCountMismatch {
+            attachment,
+            pipeline,
+            target,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Whether any factor of the blend state refers to the render pass blend constant.
+pub(crate) fn uses_blend_constant(blend: wgpu::BlendState) -> bool {
+    [blend.color, blend.alpha].iter().any(|component| {
+        [component.src_factor, component.dst_factor]
+            .iter()
+            .any(|factor| {
+                matches!(
+                    factor,
+                    wgpu::BlendFactor::Constant | wgpu::BlendFactor::OneMinusConstant
+                )
+            })
+    })
+}
+
+pub const fn align_up(num: u32, align: u32) -> u32 {
+    ((num) + ((align) - 1)) & !((align) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_row_padding_keeps_the_visible_bytes() {
+        // Two rows of 3 visible bytes, each padded to 8 with markers.
+        let padded = [
+            1, 2, 3, 0xff, 0xff, 0xff, 0xff, 0xff, 4, 5, 6, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ];
+
+        assert_eq!(strip_row_padding(&padded, 3, 8), [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn strip_row_padding_without_padding_is_a_copy() {
+        let rows = [1, 2, 3, 4, 5, 6];
+
+        assert_eq!(strip_row_padding(&rows, 3, 3), rows);
+    }
+
+    #[test]
+    fn sample_count_mismatches_name_the_attachment() {
+        let attachments = [("multisampled color", 4), ("depth/stencil", 1)];
+
+        assert!(check_sample_counts(4, &attachments[..1]).is_ok());
+        assert!(matches!(
+            check_sample_counts(4, &attachments),
+            Err(RenderError::SampleCountMismatch {
+                attachment: "depth/stencil",
+                pipeline: 4,
+                target: 1,
+            })
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga_oil::compose::{Composer, NagaModuleDescriptor, ShaderDefValue, ShaderType};
+use std::collections::HashMap;
+
+/// What a wgpu device has to offer for naga to get a capability, the reverse of
+/// `adapters::capabilities`.
+fn wgpu_requirement(capability: Capabilities) -> Option<&'static str> {
+    let requirement = match capability {
+        Capabilities::PUSH_CONSTANT => "Features::PUSH_CONSTANTS",
+        Capabilities::FLOAT64 => "Features::SHADER_F64",
+        Capabilities::PRIMITIVE_INDEX => "Features::SHADER_PRIMITIVE_INDEX",
+        Capabilities::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+        | Capabilities::SAMPLER_NON_UNIFORM_INDEXING => {
+            "Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING"
+        }
+        Capabilities::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING => {
+            "Features::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING"
+        }
+        Capabilities::STORAGE_TEXTURE_16BIT_NORM_FORMATS => "Features::TEXTURE_FORMAT_16BIT_NORM",
+        Capabilities::MULTIVIEW => "Features::MULTIVIEW",
+        Capabilities::EARLY_DEPTH_TEST => "Features::SHADER_EARLY_DEPTH_TEST",
+        Capabilities::MULTISAMPLED_SHADING => "DownlevelFlags::MULTISAMPLED_SHADING",
+        Capabilities::DUAL_SOURCE_BLENDING => "Features::DUAL_SOURCE_BLENDING",
+        Capabilities::CUBE_ARRAY_TEXTURES => "DownlevelFlags::CUBE_ARRAY_TEXTURES",
+        _ => return None,
+    };
+
+    Some(requirement)
+}
+
+/// The smallest set of capabilities the module validates with.
+///
+/// Rather than duplicating the validator's rules, this drops one capability at a time from
+/// the full set and keeps those whose absence makes validation fail.
+pub fn required_capabilities(module: &naga::Module) -> Result<Capabilities, String> {
+    let validates = |caps| {
+        Validator::new(ValidationFlags::all(), caps)
+            .validate(module)
+            .is_ok()
+    };
+
+    if !validates(Capabilities::all()) {
+        return Err("the shader does not validate even with every capability".into());
+    }
+
+    let required = Capabilities::all()
+        .iter()
+        .filter(|&capability| !validates(Capabilities::all() - capability))
+        .collect();
+
+    // Only holds while no capability can stand in for another.
+    if !validates(required) {
+        return Err(format!(
+            "the shader needs more than {required:?}, but no single capability is required"
+        ));
+    }
+
+    Ok(required)
+}
+
+/// Compose the shader and print the capabilities it needs, with the wgpu feature or
+/// downlevel flag providing each one.
+///
+/// Returns `false` if the shader could not be composed or validated.
+pub fn run(
+    source: &str,
+    shader_type: ShaderType,
+    shader_defs: &HashMap<String, ShaderDefValue>,
+) -> bool {
+    // Compose with everything allowed, validation happens in `required_capabilities`.
+    let mut composer = Composer::non_validating().with_capabilities(Capabilities::all());
+
+    let module = match composer.make_naga_module(NagaModuleDescriptor {
+        source,
+        shader_type,
+        shader_defs: shader_defs.clone(),
+        ..Default::default()
+    }) {
+        Ok(module) => module,
+        Err(e) => {
+            println!("{}", e.emit_to_string(&composer));
+            return false;
+        }
+    };
+
+    let required = match required_capabilities(&module) {
+        Ok(required) => required,
+        Err(e) => {
+            println!("{e}");
+            return false;
+        }
+    };
+
+    if required.is_empty() {
+        println!("No capabilities required");
+        return true;
+    }
+
+    for (name, capability) in required.iter_names() {
+        match wgpu_requirement(capability) {
+            Some(requirement) => println!("{name:<40} ({requirement})"),
+            None => println!("{name}"),
+        }
+    }
+
+    true
+}
@@ -0,0 +1,35 @@
+// Stretches the input texture over the whole target, render it with `--frame-uniforms`
+// and `--input-texture`, e.g. to compare `--sampler-filter nearest` and `linear`.
+
+struct Frame {
+    resolution: vec2<f32>,
+    time: f32,
+    _pad: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> frame: Frame;
+@group(0) @binding(1)
+var input: texture_2d<f32>;
+@group(0) @binding(2)
+var input_sampler: sampler;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(input, input_sampler, in.clip_position.xy / frame.resolution);
+}
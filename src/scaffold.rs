@@ -0,0 +1,81 @@
+/// Generate a minimal shader matching the pipeline built by `render::render`.
+///
+/// `uniform` adds a uniform block at `@group(0) @binding(0)` and `texture` adds a
+/// texture and sampler at bindings 1 and 2 of the same group, sampled in `fs_main`.
+pub fn generate(uniform: bool, texture: bool) -> String {
+    let mut source = String::new();
+
+    if uniform {
+        source.push_str(
+            "struct Params {
+    color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+",
+        );
+    }
+
+    if texture {
+        source.push_str(
+            "@group(0) @binding(1)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(2)
+var input_sampler: sampler;
+
+",
+        );
+    }
+
+    source.push_str(
+        "//////////////////////////////// Vertex shader ////////////////////////////////
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    out.uv = model.position * vec2<f32>(0.5, -0.5) + 0.5;
+
+    return out;
+}
+
+//////////////////////////////// Fragment shader ////////////////////////////////
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = vec4<f32>(in.uv, 0.0, 1.0);
+",
+    );
+
+    if uniform {
+        source.push_str("    color *= params.color;\n");
+    }
+
+    if texture {
+        source.push_str("    color *= textureSample(input_texture, input_sampler, in.uv);\n");
+    }
+
+    source.push_str(
+        "
+    return color;
+}
+",
+    );
+
+    source
+}
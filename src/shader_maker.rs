@@ -0,0 +1,1453 @@
+use crate::reflect::BindingInfo;
+use naga_oil::compose::preprocess::Preprocessor;
+use naga_oil::compose::{
+    get_preprocessor_data, ComposableModuleDescriptor, Composer, ComposerError, ComposerErrorInner,
+    ErrSource, NagaModuleDescriptor, ShaderDefValue, ShaderType,
+};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ShaderError {
+    /// naga_oil failed to preprocess, parse or compose the shader or a module it imports.
+    ///
+    /// `ShaderMaker::diagnostic` renders it against the source with spans.
+    Compose(Box<ComposerError>),
+    /// naga_oil composed the shader, but the result failed naga's validation.
+    ///
+    /// Like `Compose`, `ShaderMaker::diagnostic` renders it against the source with spans.
+    Invalid(Box<ComposerError>),
+    /// The composed module failed `ShaderMaker::validate`, e.g. for a backend that needs
+    /// its info from a maker that doesn't validate.
+    Validation(Box<naga::WithSpan<naga::valid::ValidationError>>),
+    /// The module has no entry point by this name.
+    MissingEntryPoint(String),
+    /// A composable module of this name is already registered with different source.
+    ModuleConflict(String),
+    /// naga's SPIR-V backend failed.
+    Spirv(naga::back::spv::Error),
+    /// naga's GLSL backend failed.
+    Glsl(naga::back::glsl::Error),
+    /// naga's WGSL backend failed.
+    Wgsl(naga::back::wgsl::Error),
+    /// A shader or module file couldn't be read.
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// A defs file isn't a flat `.toml` or `.json` map of names to bools and integers.
+    DefsFile { path: PathBuf, error: String },
+    /// wgpu rejected the shader module or a pipeline made from it, catching what naga_oil's
+    /// validation misses, such as under `--skip-validation`.
+    Wgpu(Box<wgpu::Error>),
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShaderError::Compose(error) | ShaderError::Invalid(error) => write!(f, "{error}"),
+            ShaderError::Validation(error) => write!(f, "{}", error.as_inner()),
+            ShaderError::MissingEntryPoint(name) => write!(f, "no entry point named `{name}`"),
+            ShaderError::ModuleConflict(name) => write!(
+                f,
+                "composable module `{name}` already exists with different source"
+            ),
+            ShaderError::Spirv(error) => write!(f, "failed to write SPIR-V: {error}"),
+            ShaderError::Glsl(error) => write!(f, "failed to write GLSL: {error}"),
+            ShaderError::Wgsl(error) => write!(f, "failed to write WGSL: {error}"),
+            ShaderError::Io { path, error } => {
+                write!(f, "failed to read {}: {error}", path.display())
+            }
+            ShaderError::DefsFile { path, error } => write!(f, "{}: {error}", path.display()),
+            ShaderError::Wgpu(error) => write!(f, "wgpu rejected the shader: {error}"),
+        }
+    }
+}
+
+impl From<ComposerError> for ShaderError {
+    /// Tell validation failures from the earlier stages.
+    fn from(error: ComposerError) -> Self {
+        match error.inner {
+            ComposerErrorInner::ShaderValidationError(_)
+            | ComposerErrorInner::HeaderValidationError(_) => ShaderError::Invalid(Box::new(error)),
+            _ => ShaderError::Compose(Box::new(error)),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShaderError::Compose(error) | ShaderError::Invalid(error) => Some(error.as_ref()),
+            ShaderError::Validation(error) => Some(error.as_ref()),
+            ShaderError::MissingEntryPoint(_)
+            | ShaderError::ModuleConflict(_)
+            | ShaderError::DefsFile { .. } => None,
+            ShaderError::Spirv(error) => Some(error),
+            ShaderError::Glsl(error) => Some(error),
+            ShaderError::Wgsl(error) => Some(error),
+            ShaderError::Io { error, .. } => Some(error),
+            ShaderError::Wgpu(error) => Some(error.as_ref()),
+        }
+    }
+}
+
+/// Language and stage of a shader, naga_oil's `ShaderType` in a form clap can parse.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ShaderKind {
+    #[default]
+    Wgsl,
+    GlslVertex,
+    GlslFragment,
+}
+
+impl ShaderKind {
+    /// Guess the kind from a file extension, `None` if it doesn't say, e.g. for `.glsl`.
+    pub fn from_path(path: &str) -> Option<Self> {
+        match std::path::Path::new(path).extension()?.to_str()? {
+            "wgsl" => Some(ShaderKind::Wgsl),
+            "vert" => Some(ShaderKind::GlslVertex),
+            "frag" => Some(ShaderKind::GlslFragment),
+            _ => None,
+        }
+    }
+
+    /// Use `explicit` if given, otherwise infer the kind from the path.
+    pub fn resolve(explicit: Option<Self>, path: &str) -> Result<Self, String> {
+        explicit.or_else(|| Self::from_path(path)).ok_or_else(|| {
+            format!("can't tell the shader type of {path} from its extension, pass --shader-type")
+        })
+    }
+}
+
+impl From<ShaderKind> for ShaderType {
+    fn from(kind: ShaderKind) -> Self {
+        match kind {
+            ShaderKind::Wgsl => ShaderType::Wgsl,
+            ShaderKind::GlslVertex => ShaderType::GlslVertex,
+            ShaderKind::GlslFragment => ShaderType::GlslFragment,
+        }
+    }
+}
+
+/// Parse a def given as `NAME` (a flag), `NAME=true|false`, `NAME=-3` (`Int`) or `NAME=8` (`UInt`).
+pub fn parse_def(arg: &str) -> Result<(String, ShaderDefValue), String> {
+    let Some((name, value)) = arg.split_once('=') else {
+        return Ok((arg.into(), ShaderDefValue::default()));
+    };
+
+    Ok((name.into(), parse_def_value(name, value)?))
+}
+
+/// Parse the value of def `name` the way `parse_def` does.
+pub fn parse_def_value(name: &str, value: &str) -> Result<ShaderDefValue, String> {
+    let value = match value {
+        "true" => ShaderDefValue::Bool(true),
+        "false" => ShaderDefValue::Bool(false),
+        value if value.starts_with('-') => ShaderDefValue::Int(
+            value
+                .parse()
+                .map_err(|e| format!("invalid value for `{name}`: {e}"))?,
+        ),
+        value => ShaderDefValue::UInt(
+            value
+                .parse()
+                .map_err(|e| format!("invalid value for `{name}`: {e}"))?,
+        ),
+    };
+
+    Ok(value)
+}
+
+/// Parse a def given as `NAME=VALUE` with a signed integer value, always giving an `Int`.
+pub fn parse_def_int(arg: &str) -> Result<(String, ShaderDefValue), String> {
+    let Some((name, value)) = arg.split_once('=') else {
+        return Err(format!("expected `NAME=VALUE`, got `{arg}`"));
+    };
+
+    let value = value
+        .parse()
+        .map_err(|e| format!("invalid integer for `{name}`: {e}"))?;
+
+    Ok((name.into(), ShaderDefValue::Int(value)))
+}
+
+/// How `ShaderMaker::make_shader_debug` prints a composed module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugFormat {
+    /// The naga IR as `{:#?}` prints it.
+    Ir,
+    /// The module written back out as WGSL, with imports and `#if`s expanded.
+    Wgsl,
+}
+
+/// What `ShaderMaker::add_composable_dir` registered and what it couldn't.
+#[derive(Debug, Default)]
+pub struct DirLoad {
+    /// Names of the modules added, in the order they were added.
+    pub added: Vec<String>,
+    /// Files that couldn't be read or added, with the reason.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// Every `.wgsl` file below `dir`, sorted so loading is reproducible.
+pub fn wgsl_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            wgsl_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "wgsl") {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// `a/b/c.wgsl` below `dir` becomes `a::b::c`, for files without `#define_import_path`.
+fn relative_module_name(dir: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(dir).unwrap_or(path).with_extension("");
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// The import cycle reached from module `index` by following the imports of modules that
+/// aren't `done`, as the indices along it, starting and ending with the same one.
+///
+/// Every module that isn't done imports another one that isn't, so the walk always ends
+/// up going around a cycle.
+fn import_cycle(dependencies: &[Vec<usize>], done: &[bool], index: usize) -> Vec<usize> {
+    let mut path = vec![index];
+    loop {
+        let last = path[path.len() - 1];
+        let next = dependencies[last]
+            .iter()
+            .copied()
+            .find(|&dependency| !done[dependency])
+            .expect("modules left over import another one left over");
+
+        if let Some(start) = path.iter().position(|&index| index == next) {
+            path.drain(..start);
+            path.push(next);
+            return path;
+        }
+        path.push(next);
+    }
+}
+
+fn read_source(path: &Path) -> Result<String, ShaderError> {
+    std::fs::read_to_string(path).map_err(|error| ShaderError::Io {
+        path: path.into(),
+        error,
+    })
+}
+
+/// The value of a def in a defs file, mapped to a `ShaderDefValue`.
+///
+/// Tried in order, so non-negative integers become `UInt`s and negative ones `Int`s, as
+/// for `--def`.
+#[derive(serde::Deserialize)]
+#[serde(
+    untagged,
+    expecting = "a bool or an integer, naga_oil defs have no other types"
+)]
+enum DefsFileValue {
+    Bool(bool),
+    UInt(u32),
+    Int(i32),
+}
+
+impl From<DefsFileValue> for ShaderDefValue {
+    fn from(value: DefsFileValue) -> Self {
+        match value {
+            DefsFileValue::Bool(value) => ShaderDefValue::Bool(value),
+            DefsFileValue::UInt(value) => ShaderDefValue::UInt(value),
+            DefsFileValue::Int(value) => ShaderDefValue::Int(value),
+        }
+    }
+}
+
+/// The defs of a `.toml` or `.json` file mapping names to bools and integers.
+fn load_defs_file(path: &Path) -> Result<BTreeMap<String, ShaderDefValue>, ShaderError> {
+    let source = read_source(path)?;
+    let defs: Result<BTreeMap<String, DefsFileValue>, String> =
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => serde_json::from_str(&source).map_err(|e| e.to_string()),
+            Some("toml") => toml::from_str(&source).map_err(|e| e.to_string()),
+            _ => Err("expected a `.toml` or `.json` file".into()),
+        };
+
+    defs.map(|defs| {
+        defs.into_iter()
+            .map(|(name, value)| (name, value.into()))
+            .collect()
+    })
+    .map_err(|error| ShaderError::DefsFile {
+        path: path.into(),
+        error,
+    })
+}
+
+/// A composable module as it was added, so another composer can be given the same.
+struct Registration {
+    source: String,
+    file_path: String,
+    module_name: String,
+    shader_defs: HashMap<String, ShaderDefValue>,
+}
+
+pub struct ShaderMaker {
+    composer: Composer,
+    /// Every registered composable module in the order it was added, for `fork`.
+    registrations: Vec<Registration>,
+    /// Language and stage of the sources passed to `make_*`.
+    shader_type: ShaderType,
+    /// Source size of every registered composable module, in bytes.
+    module_sizes: BTreeMap<String, usize>,
+    /// Hash of the source of every registered composable module, to tell a re-registration
+    /// of the same module from a conflicting one.
+    module_hashes: HashMap<String, u64>,
+    /// Replace a registered module added again with different source instead of failing.
+    replace_modules: bool,
+    /// Estimated IR size of every module made so far, in bytes.
+    composed_sizes: Vec<usize>,
+    /// Modules made so far, keyed by `cache_key`, or `None` with caching turned off.
+    cache: Option<HashMap<u64, naga::Module>>,
+    /// How many `make_*` calls were answered from the cache.
+    cache_hits: usize,
+    /// Defs every shader is made with, and through it the modules it imports. Those passed
+    /// to a call take precedence.
+    default_defs: HashMap<String, ShaderDefValue>,
+    /// Defs from `ShaderMakerBuilder::shader_defs_file`, for every module added and every
+    /// shader made. Those passed to a call take precedence.
+    file_defs: BTreeMap<String, ShaderDefValue>,
+    /// Text put ahead of the source of every shader made, from `prepend_source`.
+    prelude: String,
+}
+
+/// Configuration for a `ShaderMaker`, from `ShaderMaker::builder`.
+#[derive(Default)]
+pub struct ShaderMakerBuilder {
+    capabilities: naga::valid::Capabilities,
+    skip_validation: bool,
+    replace_modules: bool,
+    default_defs: HashMap<String, ShaderDefValue>,
+    file_defs: BTreeMap<String, ShaderDefValue>,
+}
+
+impl ShaderMakerBuilder {
+    /// Allow composed shaders to use the given naga capabilities, e.g. `PUSH_CONSTANT`.
+    pub fn capabilities(mut self, capabilities: naga::valid::Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Skip naga validation, as `ShaderMaker::non_validating` does.
+    pub fn skip_validation(mut self, skip: bool) -> Self {
+        self.skip_validation = skip;
+        self
+    }
+
+    /// Replace a composable module that is added again with different source, as
+    /// `ShaderMaker::set_replace_modules` does.
+    pub fn replace_modules(mut self, replace: bool) -> Self {
+        self.replace_modules = replace;
+        self
+    }
+
+    /// Make every shader with this def, unless a call sets it itself.
+    ///
+    /// Composable modules aren't registered with it, naga_oil would fix its value for them
+    /// and fail shaders setting another. Instead, it reaches the modules a shader imports
+    /// from the shader, so a call overriding it overrides it for them too.
+    pub fn shader_def(mut self, name: impl Into<String>, value: ShaderDefValue) -> Self {
+        self.default_defs.insert(name.into(), value);
+        self
+    }
+
+    /// Like `shader_def`, for each of `defs`.
+    pub fn shader_defs(mut self, defs: impl IntoIterator<Item = (String, ShaderDefValue)>) -> Self {
+        self.default_defs.extend(defs);
+        self
+    }
+
+    /// Add and make everything with the defs of a `.toml` or `.json` file mapping names
+    /// to bools and integers, e.g. `MAX_LIGHTS = 8`, on top of the `shader_def`s.
+    ///
+    /// Unlike `shader_def`, modules are registered with them too. Defs passed to a call
+    /// take precedence, so a def overridden for a shader has to be overridden for the
+    /// modules it imports as well, or naga_oil fails on the inconsistent values.
+    pub fn shader_defs_file(mut self, path: &Path) -> Result<Self, ShaderError> {
+        self.file_defs.extend(load_defs_file(path)?);
+        Ok(self)
+    }
+
+    pub fn build(self) -> ShaderMaker {
+        let composer = if self.skip_validation {
+            Composer::non_validating()
+        } else {
+            Composer::default()
+        };
+
+        let mut shader_maker =
+            ShaderMaker::with_composer(composer.with_capabilities(self.capabilities));
+        shader_maker.default_defs = self.default_defs;
+        shader_maker.file_defs = self.file_defs;
+        shader_maker.replace_modules = self.replace_modules;
+        shader_maker
+    }
+}
+
+/// Hash of everything a composed module depends on apart from the registered modules.
+fn cache_key(
+    source: &str,
+    shader_type: ShaderType,
+    shader_defs: &HashMap<String, ShaderDefValue>,
+) -> u64 {
+    let mut defs: Vec<_> = shader_defs.iter().collect();
+    defs.sort_unstable_by_key(|(name, _)| *name);
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    source.hash(&mut hasher);
+    shader_type.hash(&mut hasher);
+    defs.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Default for ShaderMaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShaderMaker {
+    pub fn new() -> Self {
+        let composer = Composer::default();
+
+        Self::with_composer(composer)
+    }
+
+    fn with_composer(composer: Composer) -> Self {
+        Self {
+            composer,
+            registrations: Vec::new(),
+            shader_type: ShaderType::Wgsl,
+            module_sizes: BTreeMap::new(),
+            module_hashes: HashMap::new(),
+            replace_modules: false,
+            composed_sizes: Vec::new(),
+            cache: Some(HashMap::new()),
+            cache_hits: 0,
+            default_defs: HashMap::new(),
+            file_defs: BTreeMap::new(),
+            prelude: String::new(),
+        }
+    }
+
+    /// Configure a maker's capabilities, validation and default defs before creating it.
+    pub fn builder() -> ShaderMakerBuilder {
+        ShaderMakerBuilder::default()
+    }
+
+    /// The default defs, overridden by the file defs, overridden by `shader_defs`.
+    fn with_default_defs(
+        &self,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> HashMap<String, ShaderDefValue> {
+        let mut defs = self.default_defs.clone();
+        defs.extend(self.with_file_defs(shader_defs));
+        defs
+    }
+
+    /// The file defs, overridden by `shader_defs`.
+    fn with_file_defs(
+        &self,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> HashMap<String, ShaderDefValue> {
+        let mut defs: HashMap<String, ShaderDefValue> =
+            self.file_defs.clone().into_iter().collect();
+        defs.extend(shader_defs);
+        defs
+    }
+
+    /// The defs read by `ShaderMakerBuilder::shader_defs_file`.
+    pub fn file_defs(&self) -> &BTreeMap<String, ShaderDefValue> {
+        &self.file_defs
+    }
+
+    /// Skip naga validation of composed shaders, trading safety for faster iteration.
+    ///
+    /// wgpu still validates the module when it is turned into a `ShaderModule`, but naga_oil
+    /// spans and diagnostics for invalid shaders are lost.
+    pub fn non_validating() -> Self {
+        Self::builder().skip_validation(true).build()
+    }
+
+    /// Allow composed shaders to use the given naga capabilities, e.g. `PUSH_CONSTANT`.
+    ///
+    /// Registered modules are dropped, so this has to come before `add_composable`.
+    pub fn with_capabilities(self, capabilities: naga::valid::Capabilities) -> Self {
+        Self {
+            default_defs: self.default_defs,
+            file_defs: self.file_defs,
+            replace_modules: self.replace_modules,
+            prelude: self.prelude,
+            ..Self::with_composer(self.composer.with_capabilities(capabilities))
+        }
+    }
+
+    /// Set the language and stage of the shaders made from now on.
+    ///
+    /// GLSL has no stage attributes, so this is the only way naga can tell a vertex shader
+    /// from a fragment shader. Composable modules are unaffected and stay WGSL.
+    pub fn set_shader_type(&mut self, shader_type: ShaderType) {
+        self.shader_type = shader_type;
+    }
+
+    /// Replace a registered composable module that is added again with different source,
+    /// e.g. after the file was edited, instead of failing with `ShaderError::ModuleConflict`.
+    ///
+    /// Like `remove_composable`, replacing a module removes the modules importing it.
+    pub fn set_replace_modules(&mut self, replace: bool) {
+        self.replace_modules = replace;
+    }
+
+    /// Turn reusing modules made from the same source, shader type and defs on or off.
+    ///
+    /// Caching is on by default. Turning it off drops everything cached so far.
+    pub fn set_caching(&mut self, enabled: bool) {
+        self.cache = enabled.then(HashMap::new);
+    }
+
+    /// Drop every cached module, so the next `make_*` call composes again.
+    pub fn clear_cache(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            cache.clear();
+        }
+    }
+
+    /// How many `make_*` calls were answered from the cache so far.
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits
+    }
+
+    /// Add a shader as a composable module so that it can be imported by other shaders.
+    ///
+    /// Adding a module that is already registered with the same source does nothing and
+    /// succeeds, with different source it fails unless `set_replace_modules` is on.
+    pub fn add_composable(
+        &mut self,
+        source: &str,
+        module_name: &str,
+        shader_defs: &[&str],
+    ) -> Result<(), ShaderError> {
+        let mut shader_defs_map: HashMap<String, ShaderDefValue> = HashMap::new();
+        for def in shader_defs.iter() {
+            shader_defs_map.insert((*def).into(), Default::default());
+        }
+
+        self.add_composable_with_values(source, module_name, shader_defs_map)
+    }
+
+    /// Add the file at `path` as a composable module, named by its `#define_import_path` or
+    /// else its file stem, and return the name.
+    pub fn add_composable_file(
+        &mut self,
+        path: &Path,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> Result<String, ShaderError> {
+        let source = read_source(path)?;
+        let (name, _, _) = get_preprocessor_data(&source);
+        let name = name.unwrap_or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+
+        self.add_composable_with_path(&source, &path.to_string_lossy(), &name, shader_defs)?;
+        Ok(name)
+    }
+
+    /// Add every `.wgsl` file below `dir` as a composable module, importers after the
+    /// modules they import.
+    ///
+    /// Modules are named by their `#define_import_path`, or by their path relative to `dir`
+    /// as `a::b::c`. Imports of modules outside `dir` have to be registered beforehand.
+    /// Files that fail, and modules importing them, are reported without stopping the rest.
+    pub fn add_composable_dir(
+        &mut self,
+        dir: &Path,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> DirLoad {
+        let mut load = DirLoad::default();
+
+        let mut files = Vec::new();
+        if let Err(e) = wgsl_files(dir, &mut files) {
+            load.failed.push((dir.into(), e.to_string()));
+            return load;
+        }
+
+        let mut paths = Vec::new();
+        let mut modules = Vec::new();
+        for path in files {
+            match std::fs::read_to_string(&path) {
+                Ok(source) => {
+                    let (name, _, _) = get_preprocessor_data(&source);
+                    let name = name.unwrap_or_else(|| relative_module_name(dir, &path));
+                    paths.push(path);
+                    modules.push((name, source));
+                }
+                Err(e) => load.failed.push((path, e.to_string())),
+            }
+        }
+
+        // Which of the other modules in the directory each one imports.
+        let dependencies: Vec<Vec<usize>> = modules
+            .iter()
+            .map(|(_, source)| {
+                let (_, imports, _) = get_preprocessor_data(source);
+                imports
+                    .iter()
+                    .filter_map(|import| crate::graph::resolve(&import.import, &modules))
+                    .filter_map(|name| modules.iter().position(|(other, _)| other == name))
+                    .collect()
+            })
+            .collect();
+
+        let mut done = vec![false; modules.len()];
+        while let Some(index) = (0..modules.len())
+            .find(|&index| !done[index] && dependencies[index].iter().all(|&dep| done[dep]))
+        {
+            done[index] = true;
+
+            let (name, source) = &modules[index];
+            let file_path = paths[index].to_string_lossy();
+            match self.add_composable_with_path(source, &file_path, name, shader_defs.clone()) {
+                Ok(()) => load.added.push(name.clone()),
+                Err(e) => load
+                    .failed
+                    .push((paths[index].clone(), self.diagnostic(&e))),
+            }
+        }
+
+        // Whatever is left imports itself through other modules, or one that does.
+        for (index, path) in paths.into_iter().enumerate() {
+            if done[index] {
+                continue;
+            }
+            let cycle = import_cycle(&dependencies, &done, index);
+            let names: Vec<&str> = cycle
+                .iter()
+                .map(|&index| modules[index].0.as_str())
+                .collect();
+            let reason = if cycle.contains(&index) {
+                format!("part of the import cycle {}", names.join(" -> "))
+            } else {
+                format!("depends on the import cycle {}", names.join(" -> "))
+            };
+            load.failed.push((path, reason));
+        }
+
+        load
+    }
+
+    /// `add_composable_dir` without defs of its own, only those of the defs file.
+    pub fn add_directory(&mut self, dir: &Path) -> DirLoad {
+        self.add_composable_dir(dir, HashMap::new())
+    }
+
+    /// Add a composable module with explicit def values, e.g. `("MAX_LIGHTS", UInt(8))`.
+    pub fn add_composable_with_values(
+        &mut self,
+        source: &str,
+        module_name: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> Result<(), ShaderError> {
+        self.add_composable_with_path(source, "", module_name, shader_defs)
+    }
+
+    /// Like `add_composable_with_values`, naming `file_path` as where the source came from
+    /// in diagnostics, including those of shaders failing inside this module.
+    pub fn add_composable_with_path(
+        &mut self,
+        source: &str,
+        file_path: &str,
+        module_name: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> Result<(), ShaderError> {
+        let shader_defs = self.with_file_defs(shader_defs);
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        source.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if self.composer.contains_module(module_name) {
+            if self.module_hashes.get(module_name) == Some(&hash) {
+                return Ok(());
+            }
+            if !self.replace_modules {
+                return Err(ShaderError::ModuleConflict(module_name.into()));
+            }
+            self.remove_composable(module_name);
+        }
+
+        let module = self
+            .composer
+            .add_composable_module(ComposableModuleDescriptor {
+                source,
+                file_path,
+                shader_defs: shader_defs.clone(),
+                as_name: Some(module_name.into()),
+                ..Default::default()
+            })
+            .map_err(ShaderError::from)?;
+
+        self.registrations.push(Registration {
+            source: source.into(),
+            file_path: file_path.into(),
+            module_name: module_name.into(),
+            shader_defs,
+        });
+        self.module_sizes.insert(module.name.clone(), source.len());
+        self.module_hashes.insert(module.name.clone(), hash);
+        // A shader importing something it couldn't find before composes differently now.
+        self.clear_cache();
+
+        Ok(())
+    }
+
+    /// Put `text` ahead of the source of every shader made from now on, after the snippets
+    /// prepended before it, for plain WGSL like shared constants that isn't a module.
+    ///
+    /// The shader is preprocessed and composed with the snippets in front, so its own lines
+    /// are reported that many lines further down, which diagnostics point out. Modules
+    /// aren't affected.
+    pub fn prepend_source(&mut self, text: &str) {
+        self.prelude.push_str(text);
+        if !text.ends_with('\n') {
+            self.prelude.push('\n');
+        }
+        self.clear_cache();
+    }
+
+    /// `source` with the prepended snippets in front.
+    fn with_prelude<'a>(&self, source: &'a str) -> Cow<'a, str> {
+        if self.prelude.is_empty() {
+            Cow::Borrowed(source)
+        } else {
+            Cow::Owned(format!("{}{source}", self.prelude))
+        }
+    }
+
+    /// Remove a composable module, returning whether it was registered.
+    ///
+    /// naga_oil removes every module importing it along with it, and shaders importing any
+    /// of them fail to compile until they are added again.
+    pub fn remove_composable(&mut self, module_name: &str) -> bool {
+        if !self.composer.contains_module(module_name) {
+            return false;
+        }
+
+        self.composer.remove_composable_module(module_name);
+        let composer = &self.composer;
+        self.module_sizes
+            .retain(|name, _| composer.contains_module(name));
+        self.module_hashes
+            .retain(|name, _| composer.contains_module(name));
+        self.registrations
+            .retain(|registration| composer.contains_module(&registration.module_name));
+        self.clear_cache();
+
+        true
+    }
+
+    /// Remove every composable module, keeping the validation and capability settings.
+    ///
+    /// Shaders importing any module fail to compile until it is added again.
+    pub fn clear_modules(&mut self) {
+        let capabilities = self.composer.capabilities;
+        self.composer = std::mem::take(&mut self.composer).with_capabilities(capabilities);
+        self.module_sizes.clear();
+        self.module_hashes.clear();
+        self.registrations.clear();
+        self.clear_cache();
+    }
+
+    /// Names of the registered composable modules, in the order they were added.
+    pub fn list_modules(&self) -> Vec<String> {
+        self.registrations
+            .iter()
+            .map(|registration| registration.module_name.clone())
+            .collect()
+    }
+
+    /// The defs the composable module `name` was added with, or `None` if it isn't registered.
+    pub fn module_defs(&self, name: &str) -> Option<HashMap<String, ShaderDefValue>> {
+        self.registrations
+            .iter()
+            .find(|registration| registration.module_name == name)
+            .map(|registration| registration.shader_defs.clone())
+    }
+
+    /// A maker with the same settings and composable modules, for composing on another
+    /// thread, as naga_oil's `Composer` can't be shared or cloned.
+    ///
+    /// The modules are added to the new composer again from the sources they were added
+    /// with, so this costs as much as registering them did. Fork once per thread, not per
+    /// shader. The cache and the memory statistics start out empty.
+    pub fn fork(&self) -> Self {
+        let composer = if self.composer.validate {
+            Composer::default()
+        } else {
+            Composer::non_validating()
+        };
+
+        let mut fork = Self {
+            shader_type: self.shader_type,
+            replace_modules: self.replace_modules,
+            default_defs: self.default_defs.clone(),
+            file_defs: self.file_defs.clone(),
+            prelude: self.prelude.clone(),
+            ..Self::with_composer(composer.with_capabilities(self.composer.capabilities))
+        };
+        fork.set_caching(self.cache.is_some());
+
+        for registration in &self.registrations {
+            fork.add_composable_with_path(
+                &registration.source,
+                &registration.file_path,
+                &registration.module_name,
+                registration.shader_defs.clone(),
+            )
+            .expect("a module that was added once can be added again the same way");
+        }
+
+        fork
+    }
+
+    /// Make a naga module using the shader.
+    pub fn make_shader(
+        &mut self,
+        source: &str,
+        shader_defs: &[&str],
+    ) -> Result<wgpu::ShaderSource<'static>, ShaderError> {
+        let mut shader_defs_map: HashMap<String, ShaderDefValue> = HashMap::new();
+        for def in shader_defs.iter() {
+            shader_defs_map.insert((*def).into(), Default::default());
+        }
+
+        self.make_shader_with_values(source, shader_defs_map)
+    }
+
+    /// Make a naga module using the shader, with explicit def values instead of flags.
+    ///
+    /// naga_oil defs are bools, `i32`s or `u32`s, there are no float defs.
+    pub fn make_shader_with_values(
+        &mut self,
+        source: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> Result<wgpu::ShaderSource<'static>, ShaderError> {
+        self.make_shader_with_path(source, "", shader_defs)
+    }
+
+    /// Like `make_shader_with_values`, naming `file_path` as where the source came from in
+    /// diagnostics.
+    pub fn make_shader_with_path(
+        &mut self,
+        source: &str,
+        file_path: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> Result<wgpu::ShaderSource<'static>, ShaderError> {
+        let module = self.make_module_with_path(source, file_path, shader_defs)?;
+
+        Ok(wgpu::ShaderSource::Naga(Cow::Owned(module)))
+    }
+
+    /// Compose the shader and list its entry points, by name and stage, in declaration order.
+    pub fn entry_points(
+        &mut self,
+        source: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> Result<Vec<(String, naga::ShaderStage)>, ShaderError> {
+        let module = self.make_module(source, shader_defs)?;
+
+        Ok(module
+            .entry_points
+            .into_iter()
+            .map(|entry_point| (entry_point.name, entry_point.stage))
+            .collect())
+    }
+
+    /// Compose the shader and reflect the resources it binds, sorted by group and binding.
+    ///
+    /// The module is validated for this even if the maker doesn't validate, the stages
+    /// using each resource come from the validator's analysis.
+    pub fn reflect(
+        &mut self,
+        source: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> Result<Vec<BindingInfo>, ShaderError> {
+        let module = self.make_module(source, shader_defs)?;
+        let info = self.validate(&module)?;
+
+        Ok(crate::reflect::bindings(&module, &info))
+    }
+
+    /// Compose the shader into a naga module, for running other backends or analysis on it.
+    ///
+    /// The module is validated unless this maker was created with `non_validating`. With
+    /// caching on, a module made from the same inputs before is cloned instead.
+    pub fn make_module(
+        &mut self,
+        source: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> Result<naga::Module, ShaderError> {
+        self.make_module_with_path(source, "", shader_defs)
+    }
+
+    /// Like `make_module`, naming `file_path` as where the source came from in diagnostics.
+    ///
+    /// The path isn't part of the cache key, the same source gives the same module anywhere.
+    pub fn make_module_with_path(
+        &mut self,
+        source: &str,
+        file_path: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> Result<naga::Module, ShaderError> {
+        let source: &str = &self.with_prelude(source);
+        let shader_defs = self.with_default_defs(shader_defs);
+        let key = cache_key(source, self.shader_type, &shader_defs);
+        if let Some(module) = self.cache.as_ref().and_then(|cache| cache.get(&key)) {
+            self.cache_hits += 1;
+            return Ok(module.clone());
+        }
+
+        let module = self
+            .composer
+            .make_naga_module(NagaModuleDescriptor {
+                source,
+                file_path,
+                shader_type: self.shader_type,
+                shader_defs,
+                ..Default::default()
+            })
+            .map_err(ShaderError::from)?;
+
+        self.composed_sizes.push(estimate_module_size(&module));
+        if let Some(cache) = &mut self.cache {
+            cache.insert(key, module.clone());
+        }
+
+        Ok(module)
+    }
+
+    /// Like `make_module_with_path`, reading the source from the file at `path`.
+    pub fn make_module_from_file(
+        &mut self,
+        path: &Path,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> Result<naga::Module, ShaderError> {
+        let source = read_source(path)?;
+        self.make_module_with_path(&source, &path.to_string_lossy(), shader_defs)
+    }
+
+    /// Render an error from this maker the way naga_oil reports it, against the
+    /// offending source with spans and colours.
+    pub fn diagnostic(&self, error: &ShaderError) -> String {
+        match error {
+            ShaderError::Compose(error) | ShaderError::Invalid(error) => {
+                let mut diagnostic = error.emit_to_string(&self.composer);
+                if let ErrSource::Constructing { source, .. } = &error.source {
+                    if !self.prelude.is_empty() && source.starts_with(&self.prelude) {
+                        diagnostic.push_str(&format!(
+                            "\nnote: the first {} lines are from `prepend_source`, the shader's own lines are that many further down",
+                            self.prelude.lines().count()
+                        ));
+                    }
+                }
+                diagnostic
+            }
+            // The module's spans point into naga_oil's sources, so only the messages can be
+            // shown, from the outermost error to its cause.
+            ShaderError::Validation(error) => {
+                let mut diagnostic = format!("validation error: {}", error.as_inner());
+                for (_, label) in error.spans() {
+                    diagnostic.push_str(&format!("\n  - {label}"));
+                }
+                let mut source = std::error::Error::source(error.as_inner());
+                while let Some(cause) = source {
+                    diagnostic.push_str(&format!("\n  = {cause}"));
+                    source = cause.source();
+                }
+                diagnostic
+            }
+            error => error.to_string(),
+        }
+    }
+
+    /// Run only naga_oil's preprocessor over the shader and return the source it produces,
+    /// with `#if`s resolved and defs substituted, but without composing in its imports.
+    ///
+    /// `#import` and `#define` lines are blanked out, and uses of imported items carry the
+    /// decorated names naga_oil gives them. As nothing is parsed, this also works for shaders
+    /// that fail to compile.
+    pub fn preprocess_to_wgsl(
+        &self,
+        source: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> Result<String, ShaderError> {
+        let source: &str = &self.with_prelude(source);
+        let compose_error = |inner| {
+            ShaderError::Compose(Box::new(ComposerError {
+                inner,
+                source: ErrSource::Constructing {
+                    path: String::new(),
+                    source: source.into(),
+                    offset: 0,
+                },
+            }))
+        };
+
+        let preprocessor = Preprocessor::default();
+        // The shader's own `#define`s apply like defs passed in, as when composing.
+        let metadata = preprocessor
+            .get_preprocessor_metadata(source, true)
+            .map_err(compose_error)?;
+        let mut shader_defs = self.with_default_defs(shader_defs);
+        shader_defs.extend(metadata.defines);
+
+        let output = preprocessor
+            .preprocess(source, &shader_defs, self.composer.validate)
+            .map_err(compose_error)?;
+
+        Ok(output.preprocessed_source)
+    }
+
+    /// Compose a shader and print the final module, to check what the imports and defs
+    /// expanded to.
+    ///
+    /// This is separate from `make_shader` so the normal path doesn't pay for the printing.
+    pub fn make_shader_debug(
+        &mut self,
+        source: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+        format: DebugFormat,
+    ) -> Result<String, ShaderError> {
+        match format {
+            DebugFormat::Ir => Ok(format!("{:#?}", self.make_module(source, shader_defs)?)),
+            DebugFormat::Wgsl => self.make_wgsl_string(source, shader_defs),
+        }
+    }
+
+    /// Validate a composed module with the capabilities of the composer, returning the
+    /// analysis backends need, e.g. for a module made by a maker that doesn't validate.
+    pub fn validate(&self, module: &naga::Module) -> Result<naga::valid::ModuleInfo, ShaderError> {
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            self.composer.capabilities,
+        )
+        .validate(module)
+        .map_err(|error| ShaderError::Validation(Box::new(error)))
+    }
+
+    /// Compose a shader and write it back out as WGSL, with imports and `#if`s expanded,
+    /// e.g. to inspect, diff or cache what the preprocessing came to.
+    pub fn make_wgsl_string(
+        &mut self,
+        source: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+    ) -> Result<String, ShaderError> {
+        let module = self.make_module(source, shader_defs)?;
+        let info = self.validate(&module)?;
+
+        naga::back::wgsl::write_string(
+            &module,
+            &info,
+            naga::back::wgsl::WriterFlags::EXPLICIT_TYPES,
+        )
+        .map_err(ShaderError::Wgsl)
+    }
+
+    /// Compose a shader and write it as SPIR-V, with the version and capabilities in `options`.
+    pub fn make_spirv(
+        &mut self,
+        source: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+        options: &naga::back::spv::Options,
+    ) -> Result<Vec<u32>, ShaderError> {
+        let module = self.make_module(source, shader_defs)?;
+        let info = self.validate(&module)?;
+
+        naga::back::spv::write_vec(&module, &info, options, None).map_err(ShaderError::Spirv)
+    }
+
+    /// Compose a shader and write one of its entry points as GLSL.
+    ///
+    /// GLSL has one entry point per shader, so the stage is taken from the one picked.
+    pub fn make_glsl(
+        &mut self,
+        source: &str,
+        shader_defs: HashMap<String, ShaderDefValue>,
+        entry_point: &str,
+        options: &naga::back::glsl::Options,
+    ) -> Result<String, ShaderError> {
+        let module = self.make_module(source, shader_defs)?;
+        let info = self.validate(&module)?;
+
+        let stage = module
+            .entry_points
+            .iter()
+            .find(|ep| ep.name == entry_point)
+            .map(|ep| ep.stage)
+            .ok_or_else(|| ShaderError::MissingEntryPoint(entry_point.into()))?;
+        let pipeline_options = naga::back::glsl::PipelineOptions {
+            shader_stage: stage,
+            entry_point: entry_point.into(),
+            multiview: None,
+        };
+
+        let mut glsl = String::new();
+        naga::back::glsl::Writer::new(
+            &mut glsl,
+            &module,
+            &info,
+            options,
+            &pipeline_options,
+            naga::proc::BoundsCheckPolicies::default(),
+        )
+        .and_then(|mut writer| writer.write())
+        .map_err(ShaderError::Glsl)?;
+
+        Ok(glsl)
+    }
+
+    /// The approximate memory used by registered and composed modules.
+    pub fn memory_report(&self) -> MemoryReport {
+        MemoryReport {
+            modules: self.module_sizes.clone(),
+            composed: self.composed_sizes.clone(),
+            cache: self
+                .cache
+                .as_ref()
+                .map(|cache| (cache.len(), self.cache_hits)),
+        }
+    }
+}
+
+/// Approximate memory used by a maker's modules, displayed as an indented listing.
+pub struct MemoryReport {
+    /// Source size of every registered composable module, in bytes.
+    pub modules: BTreeMap<String, usize>,
+    /// Estimated IR size of every module made so far, in bytes.
+    pub composed: Vec<usize>,
+    /// Entries in the module cache and the hits on it, or `None` with caching turned off.
+    pub cache: Option<(usize, usize)>,
+}
+
+impl std::fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Registered modules (source):")?;
+        for (name, size) in &self.modules {
+            writeln!(f, "    {name}: {size} bytes")?;
+        }
+        writeln!(
+            f,
+            "    total: {} bytes",
+            self.modules.values().sum::<usize>()
+        )?;
+
+        writeln!(f, "Composed modules (estimated IR):")?;
+        for (index, size) in self.composed.iter().enumerate() {
+            writeln!(f, "    #{index}: {size} bytes")?;
+        }
+        writeln!(
+            f,
+            "    total: {} bytes",
+            self.composed.iter().sum::<usize>()
+        )?;
+
+        match self.cache {
+            Some((entries, hits)) => write!(f, "Module cache: {entries} entries, {hits} hits"),
+            None => write!(f, "Module cache: off"),
+        }
+    }
+}
+
+/// Rough heap size of a naga module, counting arena entries times their item size.
+///
+/// Strings and nested blocks aren't followed, so this undercounts somewhat.
+fn estimate_module_size(module: &naga::Module) -> usize {
+    use std::mem::size_of;
+
+    let function_size = |function: &naga::Function| {
+        function.arguments.len() * size_of::<naga::FunctionArgument>()
+            + function.local_variables.len() * size_of::<naga::LocalVariable>()
+            + function.expressions.len() * size_of::<naga::Expression>()
+            + function.body.len() * size_of::<naga::Statement>()
+    };
+
+    module.types.len() * size_of::<naga::Type>()
+        + module.constants.len() * size_of::<naga::Constant>()
+        + module.global_variables.len() * size_of::<naga::GlobalVariable>()
+        + module.const_expressions.len() * size_of::<naga::Expression>()
+        + module
+            .functions
+            .iter()
+            .map(|(_, function)| function_size(function))
+            .sum::<usize>()
+        + module
+            .entry_points
+            .iter()
+            .map(|entry_point| size_of::<naga::EntryPoint>() + function_size(&entry_point.function))
+            .sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = include_str!("test.wgsl");
+
+    #[test]
+    fn identical_make_shader_calls_hit_the_cache() {
+        let mut shader_maker = ShaderMaker::new();
+
+        shader_maker.make_shader(SHADER, &["BLUE"]).unwrap();
+        assert_eq!(shader_maker.cache_hits(), 0);
+        shader_maker.make_shader(SHADER, &["BLUE"]).unwrap();
+        assert_eq!(shader_maker.cache_hits(), 1);
+
+        // Other defs are another module.
+        shader_maker.make_shader(SHADER, &[]).unwrap();
+        assert_eq!(shader_maker.cache_hits(), 1);
+    }
+
+    #[test]
+    fn cleared_or_disabled_cache_composes_again() {
+        let mut shader_maker = ShaderMaker::new();
+        shader_maker.make_shader(SHADER, &["BLUE"]).unwrap();
+
+        shader_maker.clear_cache();
+        shader_maker.make_shader(SHADER, &["BLUE"]).unwrap();
+        assert_eq!(shader_maker.cache_hits(), 0);
+
+        shader_maker.set_caching(false);
+        shader_maker.make_shader(SHADER, &["BLUE"]).unwrap();
+        shader_maker.make_shader(SHADER, &["BLUE"]).unwrap();
+        assert_eq!(shader_maker.cache_hits(), 0);
+    }
+
+    const QUALITY_MODULE: &str = r#"
+#define_import_path quality
+
+fn level() -> f32 {
+#if QUALITY == 3
+    return 0.75;
+#else
+    return 0.25;
+#endif
+}
+"#;
+
+    const QUALITY_SHADER: &str = r#"
+#import quality
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(quality::level());
+}
+"#;
+
+    #[test]
+    fn call_defs_override_default_defs_through_imports() {
+        let mut shader_maker = ShaderMaker::builder()
+            .shader_def("QUALITY", ShaderDefValue::UInt(1))
+            .build();
+        shader_maker
+            .add_composable(QUALITY_MODULE, "quality", &[])
+            .unwrap();
+
+        let default = shader_maker
+            .make_wgsl_string(QUALITY_SHADER, HashMap::new())
+            .unwrap();
+        let overridden = shader_maker
+            .make_wgsl_string(
+                QUALITY_SHADER,
+                HashMap::from([("QUALITY".into(), ShaderDefValue::UInt(3))]),
+            )
+            .unwrap();
+
+        assert!(
+            default.contains("0.25") && !default.contains("0.75"),
+            "{default}"
+        );
+        assert!(
+            overridden.contains("0.75") && !overridden.contains("0.25"),
+            "{overridden}"
+        );
+    }
+
+    const LIGHTS_SHADER: &str = r#"
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+#if MAX_LIGHTS > 4
+    let lights = f32(#{MAX_LIGHTS});
+#else
+    let lights = 1.0;
+#endif
+    return vec4<f32>(lights / 16.0);
+}
+"#;
+
+    #[test]
+    fn integer_defs_reach_conditions_and_substitutions() {
+        let def = parse_def("MAX_LIGHTS=8").unwrap();
+        assert_eq!(def, ("MAX_LIGHTS".into(), ShaderDefValue::UInt(8)));
+
+        let mut shader_maker = ShaderMaker::new();
+        let wgsl = shader_maker
+            .make_wgsl_string(LIGHTS_SHADER, HashMap::from([def]))
+            .unwrap();
+        assert!(wgsl.contains("8.0 / 16.0"), "{wgsl}");
+        assert!(!wgsl.contains("1.0"), "{wgsl}");
+
+        let wgsl = shader_maker
+            .make_wgsl_string(
+                LIGHTS_SHADER,
+                HashMap::from([parse_def("MAX_LIGHTS=2").unwrap()]),
+            )
+            .unwrap();
+        assert!(wgsl.contains("1.0"), "{wgsl}");
+    }
+
+    #[test]
+    fn memory_report_lists_modules_and_the_cache() {
+        let mut shader_maker = ShaderMaker::builder()
+            .shader_def("QUALITY", ShaderDefValue::UInt(1))
+            .build();
+        shader_maker
+            .add_composable(QUALITY_MODULE, "quality", &[])
+            .unwrap();
+        shader_maker
+            .make_wgsl_string(QUALITY_SHADER, HashMap::new())
+            .unwrap();
+
+        let report = shader_maker.memory_report();
+        assert_eq!(report.modules["quality"], QUALITY_MODULE.len());
+        assert_eq!(report.composed.len(), 1);
+        assert_eq!(report.cache, Some((1, 0)));
+        assert!(report
+            .to_string()
+            .ends_with("Module cache: 1 entries, 0 hits"));
+    }
+
+    /// Write `contents` to a file named `name` in a directory of its own for `test`.
+    fn defs_file(test: &str, name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("naga_oil_demo_{test}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn defs_files_map_to_typed_defs() {
+        let expected = BTreeMap::from([
+            ("BLUE".to_owned(), ShaderDefValue::Bool(true)),
+            ("OFFSET".to_owned(), ShaderDefValue::Int(-2)),
+            ("QUALITY".to_owned(), ShaderDefValue::UInt(3)),
+        ]);
+        let toml = defs_file(
+            "defs_files",
+            "defs.toml",
+            "# Shared by every variant\nBLUE = true\nQUALITY = 3 # best\n\"OFFSET\" = -2\n",
+        );
+        let json = defs_file(
+            "defs_files",
+            "defs.json",
+            "{\n  \"BLUE\": true,\n  \"QUALITY\": 3,\n  \"OFFSET\": -2\n}\n",
+        );
+
+        for path in [toml, json] {
+            let shader_maker = ShaderMaker::builder()
+                .shader_defs_file(&path)
+                .unwrap()
+                .build();
+            assert_eq!(shader_maker.file_defs(), &expected, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn defs_files_reject_floats_and_unknown_formats() {
+        let float = defs_file("defs_files_reject", "defs.toml", "SCALE = 1.5\n");
+        let yaml = defs_file("defs_files_reject", "defs.yaml", "SCALE: 1\n");
+
+        for path in [float, yaml] {
+            let error = ShaderMaker::builder()
+                .shader_defs_file(&path)
+                .err()
+                .unwrap();
+            assert!(matches!(error, ShaderError::DefsFile { .. }), "{error}");
+        }
+    }
+
+    #[test]
+    fn file_defs_reach_modules_and_yield_to_call_defs() {
+        let path = defs_file(
+            "file_defs_reach_modules",
+            "defs.toml",
+            "QUALITY = 3\nMAX_LIGHTS = 2\n",
+        );
+        let mut shader_maker = ShaderMaker::builder()
+            .shader_defs_file(&path)
+            .unwrap()
+            .build();
+        shader_maker
+            .add_composable(QUALITY_MODULE, "quality", &[])
+            .unwrap();
+
+        let quality = shader_maker
+            .make_wgsl_string(QUALITY_SHADER, HashMap::new())
+            .unwrap();
+        assert!(quality.contains("0.75"), "{quality}");
+
+        let lights = shader_maker
+            .make_wgsl_string(
+                LIGHTS_SHADER,
+                HashMap::from([parse_def("MAX_LIGHTS=8").unwrap()]),
+            )
+            .unwrap();
+        assert!(lights.contains("8.0 / 16.0"), "{lights}");
+    }
+
+    #[test]
+    fn add_directory_registers_importers_after_their_imports() {
+        let dir = std::env::temp_dir().join("naga_oil_demo_add_directory");
+        std::fs::create_dir_all(dir.join("lighting")).unwrap();
+        // Sorted by path, the importer comes first.
+        std::fs::write(
+            dir.join("a_shading.wgsl"),
+            "#define_import_path shading\n#import lighting::common\nfn shade() -> f32 { return lighting::common::ambient(); }\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("lighting/common.wgsl"),
+            "fn ambient() -> f32 { return 0.5; }\n",
+        )
+        .unwrap();
+
+        let mut shader_maker = ShaderMaker::new();
+        let load = shader_maker.add_directory(&dir);
+
+        assert!(load.failed.is_empty(), "{:?}", load.failed);
+        assert_eq!(load.added, ["lighting::common", "shading"]);
+    }
+}
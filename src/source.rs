@@ -0,0 +1,31 @@
+/// Whether `load` fetches the location rather than reading it from disk.
+pub fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// Read shader source from a file or, with the `url` feature, an `http(s)://` URL.
+pub fn load(location: &str) -> Result<String, String> {
+    if is_url(location) {
+        fetch(location)
+    } else {
+        std::fs::read_to_string(location).map_err(|e| format!("failed to read {location}: {e}"))
+    }
+}
+
+#[cfg(feature = "url")]
+fn fetch(url: &str) -> Result<String, String> {
+    let response = ureq::get(url)
+        .call()
+        .map_err(|e| format!("failed to fetch shader: {e}"))?;
+
+    response
+        .into_string()
+        .map_err(|e| format!("failed to read the response from {url}: {e}"))
+}
+
+#[cfg(not(feature = "url"))]
+fn fetch(url: &str) -> Result<String, String> {
+    Err(format!(
+        "can't fetch {url}: built without the `url` feature"
+    ))
+}
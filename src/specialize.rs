@@ -0,0 +1,115 @@
+use crate::render::{self, Gpu, RenderOptions};
+use crate::shader_maker::ShaderMaker;
+use naga_oil::compose::ShaderDefValue;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Def that switches a shader from its uniform block to the baked-in values.
+const SPECIALIZED_DEF: &str = "SPECIALIZED";
+
+/// Parse a `NAME=VALUE` pair given on the command line.
+pub fn parse_value(arg: &str) -> Result<(String, u32), String> {
+    let (name, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got `{arg}`"))?;
+    let value = value
+        .parse()
+        .map_err(|e| format!("invalid value for `{name}`: {e}"))?;
+
+    Ok((name.into(), value))
+}
+
+/// Pack the values the way a WGSL struct of `u32`s is laid out in a little-endian
+/// uniform buffer.
+fn uniform_bytes(values: &[(String, u32)]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = values
+        .iter()
+        .flat_map(|(_, value)| value.to_le_bytes())
+        .collect();
+
+    // Uniform structs are rounded up to a 16 byte alignment.
+    bytes.resize(render::align_up(bytes.len().max(1) as u32, 16) as usize, 0);
+
+    bytes
+}
+
+/// Compile and render one variant, returning the pixels and the time it took.
+fn render_variant(
+    gpu: &Gpu,
+    source: &str,
+    shader_defs: HashMap<String, ShaderDefValue>,
+    uniforms: Option<&[u8]>,
+) -> Option<(Vec<u8>, Duration)> {
+    let start = Instant::now();
+
+    let mut shader_maker = ShaderMaker::new();
+    let shader_source = match shader_maker.make_shader_with_values(source, shader_defs) {
+        Ok(shader_source) => shader_source,
+        Err(e) => {
+            println!("{}", shader_maker.diagnostic(&e));
+            return None;
+        }
+    };
+
+    let options = RenderOptions {
+        uniforms,
+        ..Default::default()
+    };
+    let data = match render::render(gpu, shader_source, &options) {
+        Ok(data) => data,
+        Err(e) => {
+            println!("{e}");
+            return None;
+        }
+    };
+
+    Some((data, start.elapsed()))
+}
+
+/// Render a shader once reading `values` from a uniform buffer at `@group(0) @binding(0)`,
+/// and once with `SPECIALIZED` defined and each value baked in as a def of the same name.
+///
+/// The shader is expected to branch on `#ifdef SPECIALIZED` and use `#{NAME}` in that branch,
+/// see `specialize.wgsl`. Returns `false` if either variant fails or the outputs differ.
+pub fn compare(
+    gpu: &Gpu,
+    source: &str,
+    generic_defs: HashMap<String, ShaderDefValue>,
+    values: &[(String, u32)],
+) -> bool {
+    let mut specialized_defs = generic_defs.clone();
+    specialized_defs.insert(SPECIALIZED_DEF.into(), Default::default());
+    for (name, value) in values {
+        specialized_defs.insert(name.clone(), ShaderDefValue::UInt(*value));
+    }
+
+    let uniforms = uniform_bytes(values);
+
+    let Some((generic, generic_time)) = render_variant(gpu, source, generic_defs, Some(&uniforms))
+    else {
+        println!("Generic variant failed to compile");
+        return false;
+    };
+    let Some((specialized, specialized_time)) = render_variant(gpu, source, specialized_defs, None)
+    else {
+        println!("Specialized variant failed to compile");
+        return false;
+    };
+
+    println!("Generic:     {generic_time:?}");
+    println!("Specialized: {specialized_time:?}");
+
+    let differing = generic
+        .chunks_exact(4)
+        .zip(specialized.chunks_exact(4))
+        .filter(|(a, b)| a != b)
+        .count();
+
+    if differing == 0 {
+        println!("Outputs are identical");
+    } else {
+        println!("Outputs differ in {differing} pixels");
+    }
+
+    differing == 0
+}
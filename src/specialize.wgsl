@@ -0,0 +1,52 @@
+// Color values either read from a uniform buffer or baked in at compile time.
+// Try it with: specialize src/specialize.wgsl --value RED=255 --value GREEN=128 --value BLUE=0
+
+struct Params {
+    red: u32,
+    green: u32,
+    blue: u32,
+}
+
+#ifdef SPECIALIZED
+fn params() -> Params {
+    return Params(#{RED}u, #{GREEN}u, #{BLUE}u);
+}
+#else
+@group(0) @binding(0)
+var<uniform> uniforms: Params;
+
+fn params() -> Params {
+    return uniforms;
+}
+#endif
+
+//////////////////////////////// Vertex shader ////////////////////////////////
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+
+    return out;
+}
+
+//////////////////////////////// Fragment shader ////////////////////////////////
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let p = params();
+    var color = vec3<f32>(f32(p.red), f32(p.green), f32(p.blue)) / 255.0;
+
+    return vec4<f32>(color, 1.0);
+}
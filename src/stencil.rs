@@ -0,0 +1,256 @@
+use crate::render::{self, Vertex2d, VertexBuffer};
+use std::path::Path;
+
+/// Format of the stencil attachment used for masking.
+pub const STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+/// Stencil value marking pixels the main shader may draw to.
+pub const STENCIL_REFERENCE: u32 = 1;
+
+/// Writes `STENCIL_REFERENCE` wherever the mask is brighter than 50%.
+const MASK_SHADER: &str = "
+@group(0) @binding(0)
+var mask_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var mask_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@location(0) position: vec2<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = position * vec2<f32>(0.5, -0.5) + 0.5;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if textureSample(mask_texture, mask_sampler, in.uv).r < 0.5 {
+        discard;
+    }
+    return vec4<f32>(0.0);
+}
+";
+
+/// Grayscale image deciding where the main shader is drawn.
+pub struct StencilMask {
+    pub size: (u32, u32),
+    /// One byte per pixel.
+    pub pixels: Vec<u8>,
+}
+
+impl StencilMask {
+    /// Load an image as a mask, it is stretched over the whole render target.
+    pub fn load(path: &Path) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?.to_luma8();
+
+        Ok(Self {
+            size: image.dimensions(),
+            pixels: image.into_raw(),
+        })
+    }
+}
+
+/// Stencil state for the main pipeline, drawing only where the mask pass left the reference.
+///
+/// With `depth_test`, fragments are also depth tested with it and write their depth.
+pub fn masked_depth_stencil(depth_test: Option<wgpu::CompareFunction>) -> wgpu::DepthStencilState {
+    let face = wgpu::StencilFaceState {
+        compare: wgpu::CompareFunction::Equal,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op: wgpu::StencilOperation::Keep,
+    };
+
+    wgpu::DepthStencilState {
+        format: STENCIL_FORMAT,
+        depth_write_enabled: depth_test.is_some(),
+        depth_compare: depth_test.unwrap_or(wgpu::CompareFunction::Always),
+        stencil: wgpu::StencilState {
+            front: face,
+            back: face,
+            read_mask: 0xff,
+            write_mask: 0,
+        },
+        bias: Default::default(),
+    }
+}
+
+/// Pipeline and resources drawing the mask into the stencil attachment.
+pub struct MaskPass {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    /// Its own quad, the main draw may use another vertex type.
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    sample_count: u32,
+}
+
+impl MaskPass {
+    /// `color_format`, `sample_count` and `color_targets` have to match the pass's color
+    /// attachments, nothing is written to them.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mask: &StencilMask,
+        color_format: wgpu::TextureFormat,
+        sample_count: u32,
+        color_targets: u32,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: mask.size.0,
+            height: mask.size.1,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("stencil mask texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        queue.write_texture(
+            texture.as_image_copy(),
+            &mask.pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(mask.size.0),
+                rows_per_image: Some(mask.size.1),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("stencil mask bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("stencil mask bind group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("stencil mask shader"),
+            source: wgpu::ShaderSource::Wgsl(MASK_SHADER.into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("stencil mask pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let face = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op: wgpu::StencilOperation::Replace,
+        };
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("stencil mask pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex2d::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &vec![
+                    Some(wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::empty(),
+                    });
+                    color_targets as usize
+                ],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: STENCIL_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: face,
+                    back: face,
+                    read_mask: 0xff,
+                    write_mask: 0xff,
+                },
+                bias: Default::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        });
+
+        let (vertex_buffer, index_buffer) = render::quad_buffers(device);
+
+        Self {
+            pipeline,
+            bind_group,
+            vertex_buffer,
+            index_buffer,
+            sample_count,
+        }
+    }
+
+    /// The sample count its pipeline was made for.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Draw the mask with the quad buffers already bound to the pass.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_stencil_reference(STENCIL_REFERENCE);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..6, 0, 0..1);
+    }
+}
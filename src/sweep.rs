@@ -0,0 +1,91 @@
+use naga_oil::compose::ShaderDefValue;
+
+/// A shader def swept over a range of integer values, e.g. `QUALITY=0..4`.
+#[derive(Clone, Debug)]
+pub struct DefSweep {
+    pub name: String,
+    pub values: Vec<ShaderDefValue>,
+}
+
+/// Parse `NAME=START..END` (end exclusive) or `NAME=START..=END` (end inclusive).
+///
+/// Non-negative ranges produce `UInt` defs, ranges with a negative start produce `Int` defs.
+pub fn parse_sweep(arg: &str) -> Result<DefSweep, String> {
+    let (name, range) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=START..END, got `{arg}`"))?;
+    let (start, end) = range
+        .split_once("..")
+        .ok_or_else(|| format!("expected a range like 0..4 for `{name}`, got `{range}`"))?;
+
+    let (end, inclusive) = match end.strip_prefix('=') {
+        Some(end) => (end, true),
+        None => (end, false),
+    };
+
+    let parse = |bound: &str| {
+        bound
+            .trim()
+            .parse::<i32>()
+            .map_err(|e| format!("invalid range bound `{bound}` for `{name}`: {e}"))
+    };
+    let start = parse(start)?;
+    let end = parse(end)?;
+
+    let range: Vec<i32> = if inclusive {
+        (start..=end).collect()
+    } else {
+        (start..end).collect()
+    };
+
+    if range.is_empty() {
+        return Err(format!("range for `{name}` is empty"));
+    }
+
+    let values = range
+        .into_iter()
+        .map(|value| {
+            if start < 0 {
+                ShaderDefValue::Int(value)
+            } else {
+                ShaderDefValue::UInt(value as u32)
+            }
+        })
+        .collect();
+
+    Ok(DefSweep {
+        name: name.into(),
+        values,
+    })
+}
+
+/// Every combination of the swept values, one `(name, value)` list per variant.
+///
+/// With no sweeps this is a single empty variant.
+pub fn variants(sweeps: &[DefSweep]) -> Vec<Vec<(String, ShaderDefValue)>> {
+    let mut variants = vec![Vec::new()];
+
+    for sweep in sweeps {
+        variants = variants
+            .into_iter()
+            .flat_map(|variant| {
+                sweep.values.iter().map(move |value| {
+                    let mut variant = variant.clone();
+                    variant.push((sweep.name.clone(), *value));
+                    variant
+                })
+            })
+            .collect();
+    }
+
+    variants
+}
+
+/// Format a def value the way it would be written in a shader.
+pub fn value_string(value: &ShaderDefValue) -> String {
+    match value {
+        ShaderDefValue::Bool(value) => value.to_string(),
+        ShaderDefValue::Int(value) => value.to_string(),
+        ShaderDefValue::UInt(value) => value.to_string(),
+    }
+}
@@ -0,0 +1,241 @@
+use std::path::PathBuf;
+
+/// Dimension of the input texture bound at `@group(0) @binding(1)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Dimension {
+    /// `texture_1d`, the first row of a single image.
+    #[value(name = "1d")]
+    D1,
+    /// `texture_2d`, a single image.
+    #[default]
+    #[value(name = "2d")]
+    D2,
+    /// `texture_3d`, one image per depth slice, all the same size.
+    #[value(name = "3d")]
+    D3,
+}
+
+/// How the bytes of an input image are interpreted when the shader samples them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorSpace {
+    /// `Rgba8Unorm`, the shader sees the stored values divided by 255.
+    #[default]
+    Linear,
+    /// `Rgba8UnormSrgb`, values are decoded from sRGB to linear when sampled, as for photos.
+    Srgb,
+}
+
+impl ColorSpace {
+    pub fn format(self) -> wgpu::TextureFormat {
+        match self {
+            ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+            ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+        }
+    }
+}
+
+/// How the input texture's sampler filters between texels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Filter {
+    /// Blend the nearest texels, for smooth effects.
+    #[default]
+    Linear,
+    /// Take the nearest texel as it is, for pixel art.
+    Nearest,
+}
+
+impl From<Filter> for wgpu::FilterMode {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Linear => wgpu::FilterMode::Linear,
+            Filter::Nearest => wgpu::FilterMode::Nearest,
+        }
+    }
+}
+
+/// What the input texture's sampler returns for coordinates outside of 0 to 1.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AddressMode {
+    /// The texel at the edge.
+    #[default]
+    Clamp,
+    /// The texture tiled.
+    Repeat,
+    /// The texture tiled, every other tile flipped.
+    Mirror,
+}
+
+impl From<AddressMode> for wgpu::AddressMode {
+    fn from(mode: AddressMode) -> Self {
+        match mode {
+            AddressMode::Clamp => wgpu::AddressMode::ClampToEdge,
+            AddressMode::Repeat => wgpu::AddressMode::Repeat,
+            AddressMode::Mirror => wgpu::AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+/// Filtering and addressing of the sampler bound along with the input texture.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sampler {
+    pub filter: Filter,
+    pub address_mode: AddressMode,
+}
+
+impl Sampler {
+    pub fn descriptor(self) -> wgpu::SamplerDescriptor<'static> {
+        let address_mode = self.address_mode.into();
+        let filter = self.filter.into();
+
+        wgpu::SamplerDescriptor {
+            label: Some("input sampler"),
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            mag_filter: filter,
+            min_filter: filter,
+            ..Default::default()
+        }
+    }
+}
+
+/// RGBA8 pixels for a sampled input texture.
+pub struct InputTexture {
+    pub dimension: wgpu::TextureDimension,
+    /// `Rgba8Unorm` or `Rgba8UnormSrgb`, see `ColorSpace`.
+    pub format: wgpu::TextureFormat,
+    pub sampler: Sampler,
+    pub size: wgpu::Extent3d,
+    /// Tightly packed rows, slice after slice.
+    pub pixels: Vec<u8>,
+}
+
+impl InputTexture {
+    /// Load the images making up a texture of the given dimension.
+    pub fn load(
+        dimension: Dimension,
+        color_space: ColorSpace,
+        sampler: Sampler,
+        paths: &[PathBuf],
+    ) -> Result<Self, String> {
+        let format = color_space.format();
+        let open = |path: &PathBuf| {
+            image::open(path)
+                .map(|image| image.to_rgba8())
+                .map_err(|e| format!("failed to open {}: {e}", path.display()))
+        };
+
+        match (dimension, paths) {
+            (Dimension::D1, [path]) => {
+                let image = open(path)?;
+                let width = image.width();
+                let mut pixels = image.into_raw();
+                pixels.truncate(width as usize * 4);
+
+                Ok(Self {
+                    dimension: wgpu::TextureDimension::D1,
+                    format,
+                    sampler,
+                    size: wgpu::Extent3d {
+                        width,
+                        height: 1,
+                        depth_or_array_layers: 1,
+                    },
+                    pixels,
+                })
+            }
+            (Dimension::D2, [path]) => {
+                let image = open(path)?;
+
+                Ok(Self {
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    sampler,
+                    size: wgpu::Extent3d {
+                        width: image.width(),
+                        height: image.height(),
+                        depth_or_array_layers: 1,
+                    },
+                    pixels: image.into_raw(),
+                })
+            }
+            (Dimension::D3, [first, ..]) => {
+                let (width, height) = open(first)?.dimensions();
+                let mut pixels = Vec::new();
+
+                for path in paths {
+                    let slice = open(path)?;
+                    if slice.dimensions() != (width, height) {
+                        return Err(format!(
+                            "slice {} is {:?}, but the first slice is {:?}",
+                            path.display(),
+                            slice.dimensions(),
+                            (width, height)
+                        ));
+                    }
+                    pixels.extend_from_slice(&slice);
+                }
+
+                Ok(Self {
+                    dimension: wgpu::TextureDimension::D3,
+                    format,
+                    sampler,
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: paths.len() as u32,
+                    },
+                    pixels,
+                })
+            }
+            (Dimension::D3, []) => Err("a 3D texture needs at least one slice".into()),
+            (Dimension::D1 | Dimension::D2, paths) => Err(format!(
+                "a 1D or 2D texture is loaded from one image, got {}",
+                paths.len()
+            )),
+        }
+    }
+
+    /// View dimension the shader has to declare the texture with.
+    pub fn view_dimension(&self) -> wgpu::TextureViewDimension {
+        match self.dimension {
+            wgpu::TextureDimension::D1 => wgpu::TextureViewDimension::D1,
+            wgpu::TextureDimension::D2 => wgpu::TextureViewDimension::D2,
+            wgpu::TextureDimension::D3 => wgpu::TextureViewDimension::D3,
+        }
+    }
+
+    /// Create the texture, upload the pixels and return a view and sampler for binding it.
+    pub(crate) fn upload(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> (wgpu::TextureView, wgpu::Sampler) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("input texture"),
+            size: self.size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: self.dimension,
+            format: self.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        queue.write_texture(
+            texture.as_image_copy(),
+            &self.pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.size.width * 4),
+                rows_per_image: Some(self.size.height),
+            },
+            self.size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&self.sampler.descriptor());
+
+        (view, sampler)
+    }
+}
@@ -0,0 +1,55 @@
+/// What `ShaderMaker` should turn a composed shader into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Target {
+    /// SPIR-V binary, needs `--output`.
+    Spirv,
+    /// GLSL source for a single entry point.
+    Glsl,
+    /// WGSL with imports and `#if`s expanded.
+    Wgsl,
+    /// The shader as naga_oil's preprocessor leaves it, before imports are composed in.
+    /// Also works for shaders that fail to compile.
+    Preprocessed,
+    /// naga IR dump of the composed module.
+    Ir,
+}
+
+/// Parse a SPIR-V version like `1.3`.
+pub fn parse_spirv_version(arg: &str) -> Result<(u8, u8), String> {
+    let (major, minor) = arg
+        .split_once('.')
+        .ok_or_else(|| format!("expected `MAJOR.MINOR`, got `{arg}`"))?;
+
+    let parse = |value: &str| {
+        value
+            .parse::<u8>()
+            .map_err(|e| format!("invalid SPIR-V version `{arg}`: {e}"))
+    };
+
+    Ok((parse(major)?, parse(minor)?))
+}
+
+/// Parse a SPIR-V capability by its name in the spec, like `Shader` or `Float64`.
+pub fn parse_spirv_capability(arg: &str) -> Result<naga::back::spv::Capability, String> {
+    arg.parse()
+        .map_err(|()| format!("unknown SPIR-V capability `{arg}`"))
+}
+
+/// Parse a GLSL version like `330` for desktop GLSL or `300es` for GLSL ES.
+pub fn parse_glsl_version(arg: &str) -> Result<naga::back::glsl::Version, String> {
+    let (number, es) = match arg.strip_suffix("es") {
+        Some(number) => (number, true),
+        None => (arg, false),
+    };
+
+    let number = number
+        .parse::<u16>()
+        .map_err(|e| format!("invalid GLSL version `{arg}`: {e}"))?;
+
+    // naga's writer rejects versions it doesn't support.
+    Ok(if es {
+        naga::back::glsl::Version::new_gles(number)
+    } else {
+        naga::back::glsl::Version::Desktop(number)
+    })
+}
@@ -0,0 +1,99 @@
+use naga::TypeInner;
+
+/// Parse a `NAME=VALUE` float uniform given on the command line.
+pub fn parse(arg: &str) -> Result<(String, f32), String> {
+    let (name, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, got `{arg}`"))?;
+    let value = value
+        .parse()
+        .map_err(|e| format!("invalid value for `{name}`: {e}"))?;
+
+    Ok((name.into(), value))
+}
+
+/// Pack named float uniforms into the contents of the uniform buffer at
+/// `@group(0) @binding(0)`.
+///
+/// The layout is that of a WGSL struct with one `f32` member per value, named after it,
+/// which uniform buffers lay out 4 bytes apart with no padding in between, and the buffer
+/// is rounded up to 16 bytes. Values are little-endian, as WGSL buffers are on every host. With `module`, the values go to the offsets its struct has
+/// for them instead, so it may declare them in any order, and it is an error for the
+/// struct to have a member that isn't an `f32`, or to differ from the values in names.
+pub fn pack(module: Option<&naga::Module>, values: &[(String, f32)]) -> Result<Vec<u8>, String> {
+    let offsets: Vec<u32> = match module {
+        Some(module) => struct_offsets(module, values)?,
+        None => (0..values.len() as u32).map(|index| 4 * index).collect(),
+    };
+
+    let size = offsets.iter().max().map_or(0, |offset| offset + 4);
+    let mut bytes = vec![0; crate::render::align_up(size.max(1), 16) as usize];
+    for ((_, value), offset) in values.iter().zip(offsets) {
+        let offset = offset as usize;
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    Ok(bytes)
+}
+
+/// Offsets of `values` in the struct the module's uniform buffer at `@group(0) @binding(0)` has.
+fn struct_offsets(module: &naga::Module, values: &[(String, f32)]) -> Result<Vec<u32>, String> {
+    let Some((_, variable)) = module.global_variables.iter().find(|(_, variable)| {
+        variable.space == naga::AddressSpace::Uniform
+            && variable
+                .binding
+                .as_ref()
+                .is_some_and(|binding| binding.group == 0 && binding.binding == 0)
+    }) else {
+        return Err("shader has no uniform buffer at @group(0) @binding(0)".into());
+    };
+
+    let TypeInner::Struct { members, .. } = &module.types[variable.ty].inner else {
+        return Err("the uniform buffer at @group(0) @binding(0) isn't a struct".into());
+    };
+
+    for member in members {
+        let name = member.name.as_deref().unwrap_or_default();
+        if !matches!(
+            module.types[member.ty].inner,
+            TypeInner::Scalar {
+                kind: naga::ScalarKind::Float,
+                width: 4,
+            }
+        ) {
+            return Err(format!(
+                "uniform `{name}` is a {}, only f32 ones can be given",
+                crate::reflect::type_name(module, member.ty)
+            ));
+        }
+        if !values.iter().any(|(value_name, _)| value_name == name) {
+            return Err(format!("the shader's uniform `{name}` wasn't given"));
+        }
+    }
+
+    values
+        .iter()
+        .map(|(name, _)| {
+            members
+                .iter()
+                .find(|member| member.name.as_deref() == Some(name))
+                .map(|member| member.offset)
+                .ok_or_else(|| format!("the shader's uniform struct has no `{name}`"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_packed_little_endian_and_padded() {
+        let bytes = pack(None, &[("a".into(), 1.0), ("b".into(), -2.0)]).unwrap();
+
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(bytes[..4], [0x00, 0x00, 0x80, 0x3f]);
+        assert_eq!(bytes[4..8], [0x00, 0x00, 0x00, 0xc0]);
+        assert!(bytes[8..].iter().all(|&byte| byte == 0));
+    }
+}
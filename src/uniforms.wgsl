@@ -0,0 +1,37 @@
+// Draws a disc of `radius` around the center, `strength` times as bright as the
+// background, both given on the command line:
+// `--shader src/uniforms.wgsl --uniform radius=0.5 --uniform strength=2.0`.
+
+struct Params {
+    radius: f32,
+    strength: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) position: vec2<f32>,
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    out.position = model.position;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let background = vec3<f32>(0.2, 0.1, 0.1);
+    if length(in.position) < params.radius {
+        return vec4<f32>(background * params.strength, 1.0);
+    }
+    return vec4<f32>(background, 1.0);
+}
@@ -0,0 +1,174 @@
+use crate::output::{self, ImageMetadata};
+use crate::render::{self, Gpu, RenderOptions};
+use crate::shader_maker::ShaderMaker;
+use naga_oil::compose::ShaderDefValue;
+use notify::Watcher;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How long the file has to stay untouched before a change is picked up.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Compose and render the shader at `path` into `output`, returning whether it worked.
+///
+/// On failure the diagnostic is printed and `output` is left as it was.
+fn render_once(
+    gpu: &Gpu,
+    shader_maker: &mut ShaderMaker,
+    path: &Path,
+    shader_defs: &HashMap<String, ShaderDefValue>,
+    output: &Path,
+) -> bool {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            log::error!("failed to read {}: {e}", path.display());
+            return false;
+        }
+    };
+
+    let shader_source = match shader_maker.make_shader_with_path(
+        &source,
+        &path.to_string_lossy(),
+        shader_defs.clone(),
+    ) {
+        Ok(shader_source) => shader_source,
+        Err(e) => {
+            log::error!("{}", shader_maker.diagnostic(&e));
+            return false;
+        }
+    };
+
+    let options = RenderOptions::default();
+    let data = match render::render(gpu, shader_source, &options) {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("{e}");
+            return false;
+        }
+    };
+
+    let defs: Vec<&str> = shader_defs.keys().map(String::as_str).collect();
+    let metadata = ImageMetadata::new(&path.to_string_lossy(), &defs);
+    if let Err(e) =
+        output::save_png_with_metadata(output, options.size.0, options.size.1, &data, &metadata)
+    {
+        log::error!("failed to save {}: {e}", output.display());
+        return false;
+    }
+
+    true
+}
+
+/// Where events for the file at `path` are reported, under the canonical path of its
+/// directory so they match however the path was given.
+fn watched_path(path: &Path) -> notify::Result<(PathBuf, PathBuf)> {
+    // Editors often save by replacing the file, which a watch on the file itself misses.
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let dir = dir.canonicalize()?;
+    let file = dir.join(path.file_name().unwrap_or_default());
+
+    Ok((dir, file))
+}
+
+/// Render the shader at `path` to `output`, then again every time it or one of the module
+/// files at `module_paths` changes, until the process is stopped.
+///
+/// A changed module gets all modules registered anew with a maker from `make_shader_maker`,
+/// as registering one again drops the modules importing it. Saves in quick succession are
+/// folded into one render. A shader or module that fails to compile leaves the last good
+/// image in place, the next save gets another try.
+pub fn watch_shader(
+    gpu: &Gpu,
+    mut make_shader_maker: impl FnMut() -> Result<ShaderMaker, String>,
+    path: &Path,
+    module_paths: &[PathBuf],
+    shader_defs: &HashMap<String, ShaderDefValue>,
+    output: &Path,
+) -> notify::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+
+    let (shader_dir, shader_file) = watched_path(path)?;
+    watcher.watch(&shader_dir, notify::RecursiveMode::NonRecursive)?;
+    let mut dirs = vec![shader_dir];
+    let mut module_files = Vec::new();
+    for module_path in module_paths {
+        let (dir, file) = watched_path(module_path)?;
+        if !dirs.contains(&dir) {
+            watcher.watch(&dir, notify::RecursiveMode::NonRecursive)?;
+            dirs.push(dir);
+        }
+        module_files.push(file);
+    }
+
+    let changes = |event: &notify::Event, files: &[PathBuf]| {
+        !event.kind.is_access() && event.paths.iter().any(|changed| files.contains(changed))
+    };
+
+    let mut shader_maker = match make_shader_maker() {
+        Ok(shader_maker) => Some(shader_maker),
+        Err(e) => {
+            log::error!("{e}");
+            None
+        }
+    };
+    let rendered = shader_maker
+        .as_mut()
+        .is_some_and(|shader_maker| render_once(gpu, shader_maker, path, shader_defs, output));
+    if rendered {
+        println!("Rendered {} to {}", path.display(), output.display());
+    }
+    if module_paths.is_empty() {
+        println!("Watching {} for changes", path.display());
+    } else {
+        let modules: Vec<String> = module_paths
+            .iter()
+            .map(|module_path| module_path.display().to_string())
+            .collect();
+        println!(
+            "Watching {} and {} for changes",
+            path.display(),
+            modules.join(", ")
+        );
+    }
+
+    loop {
+        let event = rx
+            .recv()
+            .map_err(|e| notify::Error::generic(&e.to_string()))??;
+        let mut modules_changed = changes(&event, &module_files);
+        if !modules_changed && !changes(&event, std::slice::from_ref(&shader_file)) {
+            continue;
+        }
+
+        // Wait until saves stop coming in before rebuilding.
+        while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+            modules_changed |= changes(&event?, &module_files);
+        }
+
+        if modules_changed || shader_maker.is_none() {
+            shader_maker = match make_shader_maker() {
+                Ok(shader_maker) => Some(shader_maker),
+                Err(e) => {
+                    log::error!("{e}");
+                    None
+                }
+            };
+        }
+
+        let rendered = shader_maker
+            .as_mut()
+            .is_some_and(|shader_maker| render_once(gpu, shader_maker, path, shader_defs, output));
+        if rendered {
+            println!("Re-rendered {} to {}", path.display(), output.display());
+        } else {
+            println!("Keeping the last good {}", output.display());
+        }
+    }
+}
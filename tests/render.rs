@@ -0,0 +1,210 @@
+use naga_oil_demo::render::{self, Gpu, GpuError, GpuOptions};
+use naga_oil_demo::texture;
+use naga_oil_demo::ShaderMaker;
+use std::collections::HashMap;
+
+/// A device on any adapter, or `None` with a note on machines without one, e.g. CI
+/// runners without a GPU, so the test passes without checking anything.
+fn gpu() -> Option<Gpu> {
+    match Gpu::new(&GpuOptions::default()) {
+        Err(GpuError::NoAdapter) => {
+            eprintln!("No GPU adapter found, skipping");
+            None
+        }
+        gpu => Some(gpu.expect("a device on the adapter found")),
+    }
+}
+
+fn shader(source: &str) -> wgpu::ShaderSource<'static> {
+    ShaderMaker::new()
+        .make_shader_with_values(source, HashMap::new())
+        .expect("the test shader compiles")
+}
+
+/// The RGBA8 pixel at `(x, y)` of tightly packed rows `width` pixels wide.
+fn pixel(pixels: &[u8], width: u32, (x, y): (u32, u32)) -> [u8; 4] {
+    let offset = 4 * (y * width + x) as usize;
+    pixels[offset..offset + 4].try_into().unwrap()
+}
+
+fn assert_close(actual: [u8; 4], expected: [u8; 4], tolerance: u8) {
+    assert!(
+        actual
+            .iter()
+            .zip(expected)
+            .all(|(actual, expected)| actual.abs_diff(expected) <= tolerance),
+        "got {actual:?}, expected {expected:?}"
+    );
+}
+
+/// The center pixel of `constant.wgsl` reads back as the color it writes.
+#[test]
+fn constant_color() {
+    let Some(gpu) = gpu() else { return };
+    let size = (64, 64);
+
+    let pixels = render::render_to_rgba(&gpu, shader(include_str!("../src/constant.wgsl")), size)
+        .expect("the render succeeds");
+
+    assert_close(pixel(&pixels, size.0, (32, 32)), [64, 128, 191, 255], 1);
+}
+
+/// At 100 pixels, rows of 400 bytes are padded to 512 for the copy, and every pixel of
+/// `coordinates.wgsl` still reads back as its own coordinates once the padding is stripped.
+#[test]
+fn padded_rows() {
+    let Some(gpu) = gpu() else { return };
+    let size = (100, 8);
+
+    let pixels =
+        render::render_to_rgba(&gpu, shader(include_str!("../src/coordinates.wgsl")), size)
+            .expect("the render succeeds");
+
+    assert_eq!(pixels.len(), 4 * 100 * 8);
+    assert_eq!(pixel(&pixels, size.0, (37, 5)), [37, 5, 0, 255]);
+    assert_eq!(pixel(&pixels, size.0, (99, 7)), [99, 7, 0, 255]);
+}
+
+/// `translucent.wgsl` draws 50% red, which alpha blending mixes half and half with a
+/// black background while alpha stays opaque as 0.5 + 1.0 * (1 - 0.5).
+#[test]
+fn alpha_blending() {
+    let Some(gpu) = gpu() else { return };
+    let size = (64, 64);
+    let options = render::RenderOptions {
+        size,
+        clear_color: Some(wgpu::Color::BLACK),
+        blend: render::Blend::Alpha.state(),
+        ..Default::default()
+    };
+
+    let pixels = render::render(
+        &gpu,
+        shader(include_str!("../src/translucent.wgsl")),
+        &options,
+    )
+    .expect("the render succeeds");
+
+    assert_close(pixel(&pixels, size.0, (32, 32)), [128, 0, 0, 255], 1);
+}
+
+/// `sampled.wgsl` stretching a black and a white texel over the target, sampled with
+/// `filter`, as the red value of the pixel just left of the middle.
+fn sample_texel_edge(gpu: &Gpu, filter: texture::Filter) -> u8 {
+    let size = (64, 64);
+    let input = texture::InputTexture {
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        sampler: texture::Sampler {
+            filter,
+            ..Default::default()
+        },
+        size: wgpu::Extent3d {
+            width: 2,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        pixels: vec![0, 0, 0, 255, 255, 255, 255, 255],
+    };
+    let uniforms = render::FrameUniforms::new(0.0, size);
+    let options = render::RenderOptions {
+        size,
+        uniforms: Some(bytemuck::bytes_of(&uniforms)),
+        input_texture: Some(&input),
+        ..Default::default()
+    };
+
+    let pixels = render::render(gpu, shader(include_str!("../src/sampled.wgsl")), &options)
+        .expect("the render succeeds");
+    pixel(&pixels, size.0, (size.0 / 2 - 1, size.1 / 2))[0]
+}
+
+/// Nearest filtering keeps the texel edge sharp where linear filtering blends it.
+#[test]
+fn sampler_filter() {
+    let Some(gpu) = gpu() else { return };
+
+    assert_eq!(sample_texel_edge(&gpu, texture::Filter::Nearest), 0);
+    // Just short of the middle, linear filtering takes a little under half of the white texel.
+    let linear = sample_texel_edge(&gpu, texture::Filter::Linear);
+    assert!(
+        (64..=191).contains(&linear),
+        "got {linear}, expected about 128"
+    );
+}
+
+/// Constant blending mixes `translucent.wgsl`'s 50% red into a blue background by the
+/// blend constant, as `src * C + dst * (1 - C)` per channel.
+#[test]
+fn constant_blending() {
+    let Some(gpu) = gpu() else { return };
+    let size = (64, 64);
+    let (src, dst, constant) = (
+        [1.0, 0.0, 0.0, 0.5],
+        [0.0, 0.0, 1.0, 1.0],
+        [0.25, 0.5, 0.5, 0.5],
+    );
+    let color = |[r, g, b, a]: [f64; 4]| wgpu::Color { r, g, b, a };
+    let options = render::RenderOptions {
+        size,
+        clear_color: Some(color(dst)),
+        blend: render::Blend::Constant.state(),
+        blend_constant: Some(color(constant)),
+        ..Default::default()
+    };
+
+    let pixels = render::render(
+        &gpu,
+        shader(include_str!("../src/translucent.wgsl")),
+        &options,
+    )
+    .expect("the render succeeds");
+
+    let expected = [0, 1, 2, 3].map(|i| {
+        let mixed = src[i] * constant[i] + dst[i] * (1.0 - constant[i]);
+        (mixed * 255.0).round() as u8
+    });
+    assert_close(pixel(&pixels, size.0, (32, 32)), expected, 1);
+}
+
+/// A constant blend state without a blend constant is refused before drawing.
+#[test]
+fn constant_blending_needs_a_constant() {
+    let Some(gpu) = gpu() else { return };
+    let options = render::RenderOptions {
+        blend: render::Blend::Constant.state(),
+        ..Default::default()
+    };
+
+    let result = render::render(
+        &gpu,
+        shader(include_str!("../src/translucent.wgsl")),
+        &options,
+    );
+
+    assert!(matches!(
+        result,
+        Err(render::RenderError::MissingBlendConstant)
+    ));
+}
+
+/// Awaiting `receive` while another thread polls the device gives the pixels `wait` does.
+#[test]
+fn received_readback_matches_waited_readback() {
+    let Some(gpu) = gpu() else { return };
+    let options = render::RenderOptions::default();
+    let pending = || {
+        render::render_deferred(&gpu, shader(include_str!("../src/constant.wgsl")), &options)
+            .expect("the render is submitted")
+    };
+
+    let waited = pending().wait(&gpu.device).expect("the readback succeeds");
+    let pending = pending();
+    let received = std::thread::scope(|scope| {
+        scope.spawn(|| gpu.device.poll(wgpu::Maintain::Wait));
+        pollster::block_on(pending.receive(&gpu.device))
+    })
+    .expect("the readback succeeds");
+
+    assert_eq!(received, waited);
+}